@@ -0,0 +1,84 @@
+//! Throughput of blob encoding and KZG commitment computation.
+//!
+//! Run with `cargo bench --bench blob_encoder`.
+
+use std::hint::black_box;
+
+use alloy::primitives::Bytes;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use taiko_forced_inclusion_toolbox::{
+    blob::{
+        BlobEncoder, MAX_BLOB_DATA_SIZE, create_blob_from_data,
+        create_blob_sidecar_from_data_blocking,
+    },
+    cli::{BlobEncoderOptions, BlobSidecarVersion},
+};
+use tokio::runtime::Runtime;
+
+/// Number of payloads encoded per iteration in the worker pool benchmark.
+const PAYLOADS: usize = 8;
+
+/// A payload filling exactly one blob with non-trivial data.
+fn full_blob_payload() -> Bytes {
+    (0..MAX_BLOB_DATA_SIZE).map(|i| (i % 251) as u8).collect()
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let data = full_blob_payload();
+
+    let mut group = c.benchmark_group("encode");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("create_blob_from_data", |b| {
+        b.iter(|| create_blob_from_data(black_box(&data)).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_sidecar(c: &mut Criterion) {
+    let data = full_blob_payload();
+
+    let mut group = c.benchmark_group("sidecar");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(10);
+    for version in [BlobSidecarVersion::Eip4844, BlobSidecarVersion::Eip7594] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{version:?}")),
+            &version,
+            |b, &version| {
+                b.iter(|| {
+                    create_blob_sidecar_from_data_blocking(black_box(&data), version).unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_encoder_pool(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let payloads = vec![full_blob_payload(); PAYLOADS];
+
+    let mut group = c.benchmark_group("encoder_pool");
+    group.throughput(Throughput::Bytes((PAYLOADS * MAX_BLOB_DATA_SIZE) as u64));
+    group.sample_size(10);
+    for workers in [1, 2, 4, 8] {
+        let encoder = BlobEncoder::new(BlobEncoderOptions {
+            blob_workers: workers,
+            ..Default::default()
+        })
+        .unwrap();
+
+        group.bench_with_input(BenchmarkId::new("workers", workers), &workers, |b, _| {
+            b.to_async(&rt).iter(|| async {
+                encoder
+                    .encode_many(payloads.clone(), BlobSidecarVersion::Eip4844)
+                    .await
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_sidecar, bench_encoder_pool);
+criterion_main!(benches);
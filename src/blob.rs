@@ -1,13 +1,26 @@
 // Imported from: mk1 src/primitives/blob.rs
 
-use std::sync::LazyLock;
+use std::{
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::{Arc, Mutex},
+    thread,
+};
 
-use alloy::primitives::Bytes;
+use alloy::primitives::{Bytes, bytes};
 use alloy::{
     consensus::{Blob, BlobTransactionSidecar},
-    eips::eip4844::BYTES_PER_BLOB,
+    eips::{
+        eip4844::BYTES_PER_BLOB,
+        eip7594::{BlobTransactionSidecarEip7594, BlobTransactionSidecarVariant},
+    },
+    network::TransactionBuilder,
+    providers::Provider,
+    rpc::types::TransactionRequest,
 };
-use tokio::runtime::{Builder, Runtime};
+use futures::future::try_join_all;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::cli::{BlobEncoderOptions, BlobSidecarVersion};
 
 // Constants
 const ENCODING_VERSION: u8 = 0;
@@ -21,20 +34,6 @@ const ROUNDS: usize = 1024;
 /// - This gives us 130044 bytes of usable space per blob
 pub const MAX_BLOB_DATA_SIZE: usize = (4 * 31 + 3) * 1024 - 4; // (127 * 1024) - 4 = 130044
 
-/// Define a custom thread pool with larger stack size for blob encoding.
-///
-/// The reason for this is that if we tried to `tokio::spawn()` a task and call
-/// `BlobTransactionSidecar::try_from_blobs_bytes` inside it, the binary panics with a
-/// "tokio runtime: stack overflow" error. The default stack size is 2MB which is not enough here.
-static BLOB_THREAD_POOL: LazyLock<Runtime> = LazyLock::new(|| {
-    Builder::new_multi_thread()
-        .thread_name("blob-worker")
-        .worker_threads(2)
-        .thread_stack_size(8 * 1024 * 1024) // 8MB stack size
-        .build()
-        .expect("Failed to create blob worker thread pool")
-});
-
 /// An error type for blob encoding errors.
 #[derive(Debug, thiserror::Error)]
 #[allow(missing_docs)]
@@ -46,15 +45,183 @@ pub enum BlobError {
     #[error("KZG error: {0}")]
     KZGError(Box<dyn std::error::Error + Sync + Send>),
     #[error("thread panicked: {0}")]
-    ThreadPanicked(#[from] tokio::task::JoinError),
+    ThreadPanicked(String),
+    #[error("blob encoder workers are shut down")]
+    EncoderClosed,
+}
+
+/// The result of encoding a payload into a blob sidecar.
+type EncodeResult = Result<BlobTransactionSidecarVariant, BlobError>;
+
+/// A payload waiting in the [`BlobEncoder`] queue.
+struct EncodeJob {
+    data: Bytes,
+    version: BlobSidecarVersion,
+    reply: oneshot::Sender<EncodeResult>,
 }
 
-/// Encodes the provided input data into a list of blobs, and returns a sidecar.
+/// A pool of dedicated worker threads that encode payloads into blob sidecars.
+///
+/// Workers are plain OS threads with a configurable stack size. The reason for this is that
+/// if we tried to `tokio::spawn()` a task and compute the KZG commitments inside it, the binary
+/// panics with a "tokio runtime: stack overflow" error. The default stack size is 2MB which is
+/// not enough here.
+///
+/// Payloads are submitted through a bounded queue: once it's full, [`BlobEncoder::encode`]
+/// waits for a worker to free up a slot. Workers exit once the encoder is dropped.
+#[derive(Debug, Clone)]
+pub struct BlobEncoder {
+    jobs: mpsc::Sender<EncodeJob>,
+}
+
+impl BlobEncoder {
+    /// Spawns the worker threads of a new blob encoder.
+    pub fn new(opts: BlobEncoderOptions) -> std::io::Result<Self> {
+        let (jobs, queue) = mpsc::channel(opts.blob_queue_capacity.max(1));
+        let queue = Arc::new(Mutex::new(queue));
+
+        for i in 0..opts.blob_workers.max(1) {
+            let queue = Arc::clone(&queue);
+            thread::Builder::new()
+                .name(format!("blob-worker-{i}"))
+                .stack_size(opts.blob_worker_stack_size)
+                .spawn(move || run_blob_worker(&queue))?;
+        }
+
+        Ok(Self { jobs })
+    }
+
+    /// Encodes the provided input data into a list of blobs, and returns a sidecar
+    /// in the requested format.
+    ///
+    /// [`BlobSidecarVersion::Auto`] must be resolved with [`resolve_sidecar_version`] first;
+    /// if it isn't, the EIP-4844 format is used.
+    pub async fn encode(&self, data: Bytes, version: BlobSidecarVersion) -> EncodeResult {
+        let (reply, result) = oneshot::channel();
+        let job = EncodeJob {
+            data,
+            version,
+            reply,
+        };
+
+        self.jobs
+            .send(job)
+            .await
+            .map_err(|_| BlobError::EncoderClosed)?;
+        result.await.map_err(|_| BlobError::EncoderClosed)?
+    }
+
+    /// Encodes many payloads concurrently across the worker pool, returning the sidecars
+    /// in the same order as the payloads.
+    pub async fn encode_many<I>(
+        &self,
+        payloads: I,
+        version: BlobSidecarVersion,
+    ) -> Result<Vec<BlobTransactionSidecarVariant>, BlobError>
+    where
+        I: IntoIterator<Item = Bytes>,
+    {
+        try_join_all(payloads.into_iter().map(|data| self.encode(data, version))).await
+    }
+}
+
+/// Main loop of a blob worker thread: encode queued payloads until the encoder is dropped.
+fn run_blob_worker(queue: &Mutex<mpsc::Receiver<EncodeJob>>) {
+    loop {
+        // The lock is only held while waiting for the next job, not while encoding it.
+        let Some(job) = queue
+            .lock()
+            .expect("blob queue lock poisoned")
+            .blocking_recv()
+        else {
+            return;
+        };
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            create_blob_sidecar_from_data_blocking(&job.data, job.version)
+        }))
+        .unwrap_or_else(|panic| {
+            let msg = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(BlobError::ThreadPanicked(msg))
+        });
+
+        // The caller may have stopped waiting for the result, which is fine.
+        let _ = job.reply.send(result);
+    }
+}
+
+/// Init code that executes the `CLZ` opcode (EIP-7939) and returns its result:
+/// `PUSH1 0 CLZ PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN`.
+///
+/// `CLZ` ships with the Osaka (Fusaka) hardfork, so an `eth_call` running this code
+/// only succeeds once the L1 has activated PeerDAS and requires EIP-7594 sidecars.
+const OSAKA_PROBE_INIT_CODE: Bytes = bytes!("60001e60005260206000f3");
+
+/// Resolves the requested sidecar version into a concrete one.
+///
+/// [`BlobSidecarVersion::Auto`] is resolved by probing the L1 for the Osaka fork.
+pub async fn resolve_sidecar_version<P: Provider>(
+    requested: BlobSidecarVersion,
+    l1: &P,
+) -> eyre::Result<BlobSidecarVersion> {
+    if requested != BlobSidecarVersion::Auto {
+        return Ok(requested);
+    }
+
+    let probe = TransactionRequest::default().with_deploy_code(OSAKA_PROBE_INIT_CODE);
+    match l1.call(probe).await {
+        Ok(_) => Ok(BlobSidecarVersion::Eip7594),
+        // `CLZ` is an invalid opcode before Osaka. Any other error, e.g. a rate limit, doesn't
+        // tell the fork apart, so it's returned instead of guessing.
+        Err(e)
+            if e.as_error_resp()
+                .is_some_and(|p| is_invalid_opcode(&p.message)) =>
+        {
+            Ok(BlobSidecarVersion::Eip4844)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Error messages returned by the L1 clients when executing an undefined opcode.
+const INVALID_OPCODE_ERRORS: &[&str] = &[
+    // geth, erigon
+    "invalid opcode",
+    // reth, anvil
+    "opcode not found",
+    "opcodenotfound",
+    "notactivated",
+    // nethermind
+    "badinstruction",
+    "bad instruction",
+    // besu
+    "invalid_operation",
+    "undefined instruction",
+];
+
+/// Whether an `eth_call` error message reports an undefined opcode.
+fn is_invalid_opcode(message: &str) -> bool {
+    let message = message.to_lowercase();
+    INVALID_OPCODE_ERRORS
+        .iter()
+        .any(|error| message.contains(error))
+}
+
+/// Encodes the provided input data into a list of blobs, and returns a sidecar
+/// in the requested format.
 ///
 /// This operation blocks the current thread until the encoding is complete.
+///
+/// [`BlobSidecarVersion::Auto`] must be resolved with [`resolve_sidecar_version`] first;
+/// if it isn't, the EIP-4844 format is used.
 pub fn create_blob_sidecar_from_data_blocking(
     data: &[u8],
-) -> Result<BlobTransactionSidecar, BlobError> {
+    version: BlobSidecarVersion,
+) -> Result<BlobTransactionSidecarVariant, BlobError> {
     // Split the input data into chunks of `MAX_BLOB_DATA_SIZE` and encode each chunk into a blob
     let blobs = data
         .chunks(MAX_BLOB_DATA_SIZE)
@@ -62,20 +229,19 @@ pub fn create_blob_sidecar_from_data_blocking(
         .collect::<Result<Vec<_>, _>>()?;
 
     // Create a sidecar from the blob bytes (blocking)
-    BlobTransactionSidecar::try_from_blobs_bytes(blobs)
-        .map_err(|e| BlobError::KZGError(Box::new(e)))
-}
-
-/// Encodes the provided input data into a list of blobs, and returns a sidecar.
-///
-/// This function is async and uses a blocking thread pool with custom stack size for blob encoding.
-pub async fn create_blob_sidecar_from_data_async(
-    data: Bytes,
-) -> Result<BlobTransactionSidecar, BlobError> {
-    BLOB_THREAD_POOL
-        .spawn_blocking(move || create_blob_sidecar_from_data_blocking(&data))
-        .await
-        .map_err(BlobError::ThreadPanicked)?
+    match version {
+        BlobSidecarVersion::Eip7594 => {
+            let blobs = blobs.iter().map(|b| Blob::from_slice(b)).collect();
+            BlobTransactionSidecarEip7594::try_from_blobs(blobs)
+                .map(BlobTransactionSidecarVariant::Eip7594)
+                .map_err(|e| BlobError::KZGError(Box::new(e)))
+        }
+        BlobSidecarVersion::Eip4844 | BlobSidecarVersion::Auto => {
+            BlobTransactionSidecar::try_from_blobs_bytes(blobs)
+                .map(BlobTransactionSidecarVariant::Eip4844)
+                .map_err(|e| BlobError::KZGError(Box::new(e)))
+        }
+    }
 }
 
 /// Encodes the provided input data into the blob.
@@ -222,3 +388,215 @@ fn write_31_bytes(out: &mut [u8; BYTES_PER_BLOB], write_offset: &mut usize, buf:
     out[*write_offset..*write_offset + 31].copy_from_slice(buf);
     *write_offset += 31;
 }
+
+// The expected blobs below are derived by hand from the encoding layout, and decoded with a
+// port of the op-service decoder: they aren't vectors produced by the Go implementation.
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn only_invalid_opcodes_mean_pre_osaka() {
+        assert!(is_invalid_opcode("invalid opcode: opcode 0x1e not defined"));
+        assert!(is_invalid_opcode("EVM error OpcodeNotFound"));
+        assert!(is_invalid_opcode("BadInstruction"));
+        assert!(!is_invalid_opcode("rate limit exceeded"));
+        assert!(!is_invalid_opcode("execution reverted"));
+    }
+
+    /// Decodes a blob produced by [`create_blob_from_data`] back into its data.
+    ///
+    /// Ported from: <https://github.com/ethereum-optimism/optimism/blob/0e4b867e08ed4dfcb5f1a76693f17392b189a7f6/op-service/eth/blob.go>
+    fn decode_blob_data(blob: &[u8]) -> Vec<u8> {
+        assert_eq!(blob[1], ENCODING_VERSION, "invalid encoding version");
+        let len = u32::from_be_bytes([0, blob[2], blob[3], blob[4]]) as usize;
+        assert!(len <= MAX_BLOB_DATA_SIZE, "invalid length: {len}");
+
+        // Round 0 only carries 27 bytes of data in its first field element.
+        let mut out = vec![0u8; BYTES_PER_BLOB];
+        out[0..27].copy_from_slice(&blob[5..32]);
+
+        let mut opos = 28;
+        let mut ipos = 32;
+        let mut encoded = [blob[0], 0, 0, 0];
+        for round in 0..ROUNDS {
+            if round > 0 && opos >= len {
+                break;
+            }
+
+            // Save the first byte of each field element for later re-assembly.
+            let first = if round == 0 { 1 } else { 0 };
+            for byte in &mut encoded[first..] {
+                *byte = blob[ipos];
+                out[opos..opos + 31].copy_from_slice(&blob[ipos + 1..ipos + 32]);
+                opos += 32;
+                ipos += 32;
+            }
+
+            // Reassemble the 3 bytes spread over the 6-bit chunks of the field elements.
+            opos -= 1;
+            out[opos - 96] = (encoded[0] & 0b0011_1111) | ((encoded[1] & 0b0011_0000) << 2);
+            out[opos - 64] = (encoded[1] & 0b0000_1111) | ((encoded[3] & 0b0000_1111) << 4);
+            out[opos - 32] = (encoded[2] & 0b0011_1111) | ((encoded[3] & 0b0011_0000) << 2);
+        }
+
+        out.truncate(len);
+        out
+    }
+
+    /// Asserts that every field element of the blob is below the BLS modulus, i.e. that the
+    /// two highest order bits of each field element are unset.
+    fn assert_valid_field_elements(blob: &[u8]) {
+        for (i, fe) in blob.chunks(32).enumerate() {
+            assert_eq!(
+                fe[0] & 0b1100_0000,
+                0,
+                "field element {i} overflows: {:08b}",
+                fe[0]
+            );
+        }
+    }
+
+    /// Asserts that all bytes of the blob starting from `from` are zero.
+    fn assert_zero_from(blob: &[u8], from: usize) {
+        if let Some(pos) = blob[from..].iter().position(|b| *b != 0) {
+            panic!("unexpected non-zero byte at offset {}", from + pos);
+        }
+    }
+
+    #[test]
+    fn layout_of_empty_data() {
+        let blob = create_blob_from_data(&[]).unwrap();
+
+        assert_eq!(blob.len(), BYTES_PER_BLOB);
+        assert_zero_from(&blob, 0);
+        assert!(decode_blob_data(&blob).is_empty());
+    }
+
+    #[test]
+    fn layout_of_a_single_byte() {
+        let blob = create_blob_from_data(&[0x61]).unwrap();
+
+        // 6-bit chunk, version, 3-byte length prefix, then the data.
+        assert_eq!(
+            &blob[..6],
+            &[0x00, ENCODING_VERSION, 0x00, 0x00, 0x01, 0x61]
+        );
+        assert_zero_from(&blob, 6);
+        assert_eq!(decode_blob_data(&blob), [0x61]);
+    }
+
+    #[test]
+    fn layout_of_27_bytes_fills_first_field_element() {
+        let data = [0xff; 27];
+        let blob = create_blob_from_data(&data).unwrap();
+
+        assert_eq!(&blob[..5], &[0x00, ENCODING_VERSION, 0x00, 0x00, 27]);
+        assert_eq!(&blob[5..32], &data);
+        assert_zero_from(&blob, 32);
+        assert_eq!(decode_blob_data(&blob), data);
+    }
+
+    #[test]
+    fn layout_of_28_bytes_spills_into_six_bit_chunks() {
+        let data = [0xff; 28];
+        let blob = create_blob_from_data(&data).unwrap();
+
+        // The 28th byte is split: its low 6 bits go in the first field element, and its high
+        // 2 bits in bits 4-5 of the second field element.
+        assert_eq!(&blob[..5], &[0b0011_1111, ENCODING_VERSION, 0x00, 0x00, 28]);
+        assert_eq!(&blob[5..32], &[0xff; 27]);
+        assert_eq!(blob[32], 0b0011_0000);
+        assert_zero_from(&blob, 33);
+        assert_eq!(decode_blob_data(&blob), data);
+    }
+
+    #[test]
+    fn layout_of_124_bytes_spills_into_second_round() {
+        let data = [0xff; 31 * 4];
+        let blob = create_blob_from_data(&data).unwrap();
+
+        // Round 0 carries 123 bytes, with all 6-bit chunks saturated.
+        assert_eq!(
+            &blob[..5],
+            &[0b0011_1111, ENCODING_VERSION, 0x00, 0x00, 124]
+        );
+        assert_eq!(&blob[5..32], &[0xff; 27]);
+        for fe in 1..4 {
+            assert_eq!(blob[fe * 32], 0b0011_1111, "field element {fe}");
+            assert_eq!(&blob[fe * 32 + 1..(fe + 1) * 32], &[0xff; 31]);
+        }
+
+        // The last byte is the first 31-byte chunk of round 1.
+        assert_eq!(&blob[128..130], &[0x00, 0xff]);
+        assert_zero_from(&blob, 130);
+        assert_eq!(decode_blob_data(&blob), data);
+    }
+
+    #[test]
+    fn layout_of_max_blob_data_size() {
+        let data: Vec<u8> = (0..MAX_BLOB_DATA_SIZE).map(|i| (i % 251) as u8).collect();
+        let blob = create_blob_from_data(&data).unwrap();
+
+        assert_eq!(&blob[1..5], &[ENCODING_VERSION, 0x01, 0xfb, 0xfc]);
+        assert_valid_field_elements(&blob);
+        assert_eq!(decode_blob_data(&blob), data);
+    }
+
+    #[test]
+    fn encode_input_too_large() {
+        let data = vec![0u8; MAX_BLOB_DATA_SIZE + 1];
+
+        assert!(matches!(
+            create_blob_from_data(&data),
+            Err(BlobError::InputTooLarge(len)) if len == MAX_BLOB_DATA_SIZE + 1
+        ));
+    }
+
+    #[test]
+    fn sidecar_splits_data_into_multiple_blobs() {
+        let data = vec![0xab; MAX_BLOB_DATA_SIZE + 1];
+
+        for version in [BlobSidecarVersion::Eip4844, BlobSidecarVersion::Eip7594] {
+            let sidecar = create_blob_sidecar_from_data_blocking(&data, version).unwrap();
+            assert_eq!(sidecar.blobs().len(), 2);
+            assert_eq!(sidecar.commitments().len(), 2);
+            assert_eq!(decode_blob_data(&sidecar.blobs()[1][..]), [0xab]);
+
+            match (version, sidecar) {
+                (BlobSidecarVersion::Eip4844, BlobTransactionSidecarVariant::Eip4844(s)) => {
+                    assert_eq!(s.proofs.len(), 2);
+                }
+                (BlobSidecarVersion::Eip7594, BlobTransactionSidecarVariant::Eip7594(s)) => {
+                    assert_eq!(s.cell_proofs.len(), 2 * 128);
+                }
+                (version, sidecar) => panic!("unexpected sidecar for {version:?}: {sidecar:?}"),
+            }
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn encode_round_trip(data in prop::collection::vec(any::<u8>(), 0..=MAX_BLOB_DATA_SIZE)) {
+            let blob = create_blob_from_data(&data).unwrap();
+
+            assert_valid_field_elements(&blob);
+            prop_assert_eq!(blob.len(), BYTES_PER_BLOB);
+            prop_assert_eq!(decode_blob_data(&blob), data);
+        }
+
+        #[test]
+        fn encode_small_round_trip(data in prop::collection::vec(any::<u8>(), 0..=512)) {
+            let blob = create_blob_from_data(&data).unwrap();
+
+            // Data is written in 127-byte rounds of 4 field elements, the rest stays empty.
+            let rounds = (data.len() + 4).div_ceil(127);
+            assert_zero_from(&blob, rounds * 128);
+            prop_assert_eq!(decode_blob_data(&blob), data);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use alloy::{
+    consensus::Transaction as _,
+    eips::{eip4844::DATA_GAS_PER_BLOB, eip7594::BlobTransactionSidecarVariant},
+    network::{TransactionBuilder, TransactionBuilder4844, TransactionBuilder7594},
+    primitives::{Address, Bytes, U256},
+    providers::{Provider, WalletProvider},
+    rpc::types::{Transaction, TransactionRequest},
+};
+use tokio::time::sleep;
+
+use crate::{
+    blob::{BlobEncoder, resolve_sidecar_version},
+    cli::CancelCmdOptions,
+    fees::InclusionCost,
+};
+
+/// Gas limit of a plain ETH transfer.
+const TRANSFER_GAS_LIMIT: u64 = 21_000;
+
+/// The interval between nonce checks while waiting for the cancelled nonces to be mined.
+const NONCE_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Cancel the pending L1 transactions of the signer.
+///
+/// Each pending nonce is replaced by a zero-value self-transfer with bumped fees. As blob
+/// transactions can only be replaced by other blob transactions, the replacement of a stuck
+/// blob transaction carries an empty blob. If the node doesn't expose its transaction pool, the
+/// stuck transactions are assumed to be forced inclusions, i.e. blob transactions.
+///
+/// As either the cancellation or the stuck transaction may be mined, each nonce is waited for
+/// until the signer's latest nonce passes it, and the transaction that landed is reported.
+pub async fn cancel_pending<P: Provider + WalletProvider>(
+    opts: CancelCmdOptions,
+    l1: &P,
+    encoder: &BlobEncoder,
+) -> eyre::Result<()> {
+    let sender = l1.default_signer_address();
+    let latest_nonce = l1.get_transaction_count(sender).latest().await?;
+    let pending_nonce = l1.get_transaction_count(sender).pending().await?;
+
+    if pending_nonce <= latest_nonce {
+        println!("✅ No pending L1 transactions for {sender}");
+        return Ok(());
+    }
+
+    println!(
+        "🔍 Found {} pending L1 transactions for {sender} (nonces {latest_nonce}..{pending_nonce})",
+        pending_nonce - latest_nonce
+    );
+
+    let stuck_txs = pending_txs_by_nonce(l1, sender).await;
+    let is_blob = |nonce| {
+        stuck_txs
+            .get(&nonce)
+            .is_none_or(|stuck| stuck.blob_versioned_hashes().is_some())
+    };
+    let sidecar = match (latest_nonce..pending_nonce).any(is_blob) {
+        true => {
+            let sidecar_version = resolve_sidecar_version(opts.blob_sidecar_version, l1).await?;
+            Some(encoder.encode(Bytes::new(), sidecar_version).await?)
+        }
+        false => None,
+    };
+
+    let fees = l1.estimate_eip1559_fees().await?;
+    let current = InclusionCost {
+        inclusion_fee_gwei: 0,
+        blob_base_fee: l1.get_blob_base_fee().await?,
+        max_fee_per_blob_gas: 0,
+        blob_gas: DATA_GAS_PER_BLOB,
+        max_fee_per_gas: fees.max_fee_per_gas,
+        max_priority_fee_per_gas: fees.max_priority_fee_per_gas,
+        gas_limit: TRANSFER_GAS_LIMIT,
+    };
+
+    let mut cancellations = Vec::new();
+    for nonce in latest_nonce..pending_nonce {
+        // Start from the highest of the current fees and the stuck transaction fees, if the
+        // node exposes its transaction pool, so that the bumped fees can replace it.
+        let stuck = stuck_txs.get(&nonce);
+        let mut base = current;
+        base.max_fee_per_blob_gas = current.blob_base_fee;
+        if let Some(stuck) = stuck {
+            base.max_fee_per_gas = base.max_fee_per_gas.max(stuck.max_fee_per_gas());
+            base.max_priority_fee_per_gas = base
+                .max_priority_fee_per_gas
+                .max(stuck.max_priority_fee_per_gas().unwrap_or_default());
+            base.max_fee_per_blob_gas = base
+                .max_fee_per_blob_gas
+                .max(stuck.max_fee_per_blob_gas().unwrap_or_default());
+        }
+        let bumped = base.bumped(opts.fee_bump_percent);
+
+        let mut tx = TransactionRequest::default()
+            .with_to(sender)
+            .with_value(U256::ZERO)
+            .with_nonce(nonce)
+            .with_gas_limit(TRANSFER_GAS_LIMIT)
+            .with_max_fee_per_gas(bumped.max_fee_per_gas)
+            .with_max_priority_fee_per_gas(bumped.max_priority_fee_per_gas);
+        let blob_fee = match &sidecar {
+            Some(sidecar) if is_blob(nonce) => {
+                tx.max_fee_per_blob_gas = Some(bumped.max_fee_per_blob_gas);
+                match sidecar.clone() {
+                    BlobTransactionSidecarVariant::Eip4844(sidecar) => tx.set_blob_sidecar(sidecar),
+                    BlobTransactionSidecarVariant::Eip7594(sidecar) => {
+                        tx.set_blob_sidecar_7594(sidecar)
+                    }
+                }
+                format!(", blob fee {} wei", bumped.max_fee_per_blob_gas)
+            }
+            _ => String::new(),
+        };
+
+        let pending_tx = l1.send_transaction(tx).await?;
+        println!(
+            "🚫 Sent cancellation for nonce {nonce}: {} (max fee {} wei, priority fee {} wei{blob_fee})",
+            pending_tx.tx_hash(),
+            bumped.max_fee_per_gas,
+            bumped.max_priority_fee_per_gas,
+        );
+        cancellations.push((
+            nonce,
+            *pending_tx.tx_hash(),
+            stuck.map(|stuck| *stuck.inner.tx_hash()),
+        ));
+    }
+
+    let timeout = Duration::from_secs(opts.timeout_secs);
+    let started = Instant::now();
+    let mut mined_nonce = latest_nonce;
+    for (nonce, cancellation, stuck) in cancellations {
+        while mined_nonce <= nonce {
+            if started.elapsed() >= timeout {
+                eyre::bail!(
+                    "timed out after {}s waiting for nonce {nonce} of {sender} to be mined",
+                    timeout.as_secs()
+                );
+            }
+            sleep(NONCE_POLL_INTERVAL).await;
+            match l1.get_transaction_count(sender).latest().await {
+                Ok(latest) => mined_nonce = latest,
+                Err(e) => println!("⚠️ Failed to read the L1 nonce of {sender}: {e}"),
+            }
+        }
+
+        if let Some(receipt) = l1.get_transaction_receipt(cancellation).await? {
+            println!(
+                "✅ Nonce {nonce} cancelled in block {}: {cancellation}",
+                receipt.block_number.unwrap_or_default(),
+            );
+            continue;
+        }
+        let stuck_receipt = match stuck {
+            Some(stuck) => l1.get_transaction_receipt(stuck).await?,
+            None => None,
+        };
+        match stuck_receipt {
+            Some(receipt) => println!(
+                "⚠️ Nonce {nonce} not cancelled, its original transaction was mined in block {}: {}",
+                receipt.block_number.unwrap_or_default(),
+                receipt.transaction_hash
+            ),
+            None => {
+                println!("⚠️ Nonce {nonce} not cancelled, another transaction was mined with it")
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the pending and queued transactions of the sender in the node's transaction pool,
+/// indexed by nonce. Returns an empty map if the node doesn't support `txpool_contentFrom`.
+async fn pending_txs_by_nonce<P: Provider>(l1: &P, sender: Address) -> BTreeMap<u64, Transaction> {
+    let content: BTreeMap<String, BTreeMap<String, Transaction>> =
+        match l1.client().request("txpool_contentFrom", (sender,)).await {
+            Ok(content) => content,
+            Err(e) => {
+                println!(
+                    "⚠️ Could not read stuck transactions from the txpool, using current fees: {e}"
+                );
+                return BTreeMap::new();
+            }
+        };
+
+    content
+        .into_values()
+        .flat_map(BTreeMap::into_values)
+        .map(|tx| (tx.nonce(), tx))
+        .collect()
+}
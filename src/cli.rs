@@ -1,7 +1,13 @@
+use std::path::PathBuf;
+
 use alloy::{
-    primitives::Address, signers::local::PrivateKeySigner, transports::http::reqwest::Url,
+    eips::BlockId,
+    primitives::{Address, B256, B512, Bytes, U256, utils::parse_ether},
+    providers::MULTICALL3_ADDRESS,
+    signers::local::PrivateKeySigner,
+    transports::http::reqwest::Url,
 };
-use clap::{Parser, ValueEnum};
+use clap::{Args, Parser, ValueEnum};
 
 /// Taiko forced inclusion sender fork.
 #[derive(ValueEnum, Clone, Debug)]
@@ -20,38 +26,279 @@ pub struct Cli {
     /// RPC URL of the L1 execution layer network.
     #[clap(long, env)]
     pub l1_rpc_url: Url,
-    /// RPC URL of the L2 execution layer network.
-    #[clap(long, env)]
-    pub l2_rpc_url: Url,
-    /// Private key of the forced inclusion tx signer. Needs to be funded with ETH on L1.
-    #[clap(long, env)]
-    pub l1_private_key: PrivateKeySigner,
-    /// Private key of the forced inclusion tx signer. Needs to be funded with ETH on L2.
+    /// RPC URL of the L2 execution layer network. Not needed by the read-only commands.
     #[clap(long, env)]
-    pub l2_private_key: PrivateKeySigner,
+    pub l2_rpc_url: Option<Url>,
+    /// L1 and L2 signer options.
+    #[clap(flatten)]
+    pub signers: SignerOptions,
     /// Address of the forced inclusion store contract on L1.
     #[clap(long, env)]
     pub forced_inclusion_store_address: Address,
+    /// Address of the Pacaya TaikoInbox contract on L1, used to read the current batch ID.
+    /// Read from the forced inclusion store if not set.
+    #[clap(long, env)]
+    pub taiko_inbox_address: Option<Address>,
     /// Which fork to use (default: Shasta)
     #[arg(long, env, default_value = "shasta")]
     pub fork: Fork,
+    /// Blob encoding worker pool options.
+    #[clap(flatten)]
+    pub blob_encoder: BlobEncoderOptions,
+    /// Path to the local SQLite database remembering the sent forced inclusions.
+    #[clap(long, env, default_value = "forced-inclusions.db")]
+    pub state_db: PathBuf,
+}
+
+/// An error returned when a setting required by the command isn't configured.
+#[derive(Debug, thiserror::Error)]
+#[error("{0} is required by this command")]
+pub struct MissingConfigError(pub &'static str);
+
+/// Signers of the L1 forced inclusion tx and of the force-included L2 tx.
+///
+/// Each signer is loaded from its private key, its keystore, the remote signer, or the
+/// mnemonic, in this order.
+#[derive(Debug, Clone, Args)]
+pub struct SignerOptions {
+    /// Private key of the forced inclusion tx signer. Needs to be funded with ETH on L1.
+    #[clap(long, env)]
+    pub l1_private_key: Option<PrivateKeySigner>,
+    /// Path to the encrypted JSON keystore of the L1 signer.
+    #[clap(long, env, conflicts_with = "l1_private_key")]
+    pub l1_keystore: Option<PathBuf>,
+    /// File containing the password of the L1 keystore. Prompted for if not set.
+    #[clap(long, env)]
+    pub l1_keystore_password_file: Option<PathBuf>,
+    /// Private key of the forced inclusion tx signer. Needs to be funded with ETH on L2.
+    #[clap(long, env)]
+    pub l2_private_key: Option<PrivateKeySigner>,
+    /// Path to the encrypted JSON keystore of the L2 signer.
+    #[clap(long, env, conflicts_with = "l2_private_key")]
+    pub l2_keystore: Option<PathBuf>,
+    /// File containing the password of the L2 keystore. Prompted for if not set.
+    #[clap(long, env)]
+    pub l2_keystore_password_file: Option<PathBuf>,
+    /// URL of a Web3Signer-compatible remote signer holding the signer keys.
+    #[clap(long, env)]
+    pub remote_signer_url: Option<Url>,
+    /// Uncompressed secp256k1 public key, without its `0x04` prefix, of the L1 signer key held
+    /// by the remote signer, which identifies it.
+    #[clap(long, env, requires = "remote_signer_url")]
+    pub l1_remote_signer_public_key: Option<B512>,
+    /// Uncompressed secp256k1 public key, without its `0x04` prefix, of the L2 signer key held
+    /// by the remote signer, which identifies it.
+    #[clap(long, env, requires = "remote_signer_url")]
+    pub l2_remote_signer_public_key: Option<B512>,
+    /// BIP-39 mnemonic to derive the signers from, if they have no other signer configured.
+    #[clap(long, env)]
+    pub mnemonic: Option<String>,
+    /// Derivation path of the mnemonic accounts, without the account index.
+    #[clap(long, env, default_value = "m/44'/60'/0'/0")]
+    pub mnemonic_derivation_path: String,
+    /// Index of the L1 signer account derived from the mnemonic.
+    #[clap(long, env, default_value_t = 0)]
+    pub l1_mnemonic_index: u32,
+    /// Index of the L2 signer account derived from the mnemonic.
+    #[clap(long, env, default_value_t = 0)]
+    pub l2_mnemonic_index: u32,
+}
+
+impl Default for SignerOptions {
+    fn default() -> Self {
+        Self {
+            l1_private_key: None,
+            l1_keystore: None,
+            l1_keystore_password_file: None,
+            l2_private_key: None,
+            l2_keystore: None,
+            l2_keystore_password_file: None,
+            remote_signer_url: None,
+            l1_remote_signer_public_key: None,
+            l2_remote_signer_public_key: None,
+            mnemonic: None,
+            mnemonic_derivation_path: "m/44'/60'/0'/0".to_string(),
+            l1_mnemonic_index: 0,
+            l2_mnemonic_index: 0,
+        }
+    }
+}
+
+/// Load environment variables from the `ENV_FILE` or `.env` file.
+///
+/// Variables left empty in the file are skipped, so that optional settings can stay blank,
+/// and variables already set in the environment take precedence.
+///
+/// This must be called before any other thread is spawned, e.g. before starting the tokio
+/// runtime, as setting environment variables isn't thread-safe.
+pub fn load_env() -> eyre::Result<()> {
+    let vars = if let Ok(custom_env_file) = std::env::var("ENV_FILE") {
+        // Try from custom env file, and abort if it fails
+        dotenvy::from_filename_iter(custom_env_file)?
+    } else {
+        // Try from default .env file, and ignore if it fails. It might
+        // be that the user isn't using it.
+        match dotenvy::dotenv_iter() {
+            Ok(vars) => vars,
+            Err(_) => return Ok(()),
+        }
+    };
+
+    for var in vars {
+        let (key, value) = var?;
+        if !value.is_empty() && std::env::var_os(&key).is_none() {
+            // SAFETY: the environment is loaded before any other thread is spawned, see above.
+            unsafe { std::env::set_var(key, value) };
+        }
+    }
+
+    Ok(())
+}
+
+/// Options for the blob encoding worker pool.
+#[derive(Debug, Clone, Copy, Args)]
+pub struct BlobEncoderOptions {
+    /// Number of worker threads encoding blobs and computing KZG commitments.
+    #[clap(long, env, default_value_t = 2)]
+    pub blob_workers: usize,
+    /// Stack size of each blob worker thread, in bytes.
+    #[clap(long, env, default_value_t = 8 * 1024 * 1024)]
+    pub blob_worker_stack_size: usize,
+    /// Maximum number of payloads waiting to be encoded before senders are throttled.
+    #[clap(long, env, default_value_t = 16)]
+    pub blob_queue_capacity: usize,
+}
+
+impl Default for BlobEncoderOptions {
+    fn default() -> Self {
+        Self {
+            blob_workers: 2,
+            blob_worker_stack_size: 8 * 1024 * 1024,
+            blob_queue_capacity: 16,
+        }
+    }
 }
 
 /// Command to execute.
 #[derive(Debug, Parser)]
 pub enum Cmd {
     /// Read the forced inclusion queue from the contract.
-    ReadQueue,
+    ReadQueue(ReadQueueOptions),
+    /// Compare two queue snapshots saved by `read-queue --snapshot`.
+    DiffQueue(DiffQueueOptions),
     /// Monitor the forced inclusion queue, printing new additions/removals.
     MonitorQueue,
     /// Send a forced inclusion transaction.
     Send(SendCmdOptions),
     /// Send forced inclusion transactions in a loop.
     Spam(SpamCmdOptions),
+    /// Cancel the pending L1 transactions of the signer, e.g. stuck forced inclusions.
+    Cancel(CancelCmdOptions),
+    /// Validate the configuration and check that both signers can afford a forced inclusion.
+    Preflight,
+    /// Refresh and list the forced inclusions sent by `send` and `spam`.
+    Status,
+}
+
+/// Options for the read-queue command.
+#[derive(Debug, Clone, Args)]
+pub struct ReadQueueOptions {
+    /// Queue index to start reading from. Defaults to the queue head.
+    #[clap(long)]
+    pub start: Option<u64>,
+    /// Maximum number of queue entries to read. With `--reverse`, the newest ones are read.
+    #[clap(long)]
+    pub limit: Option<u64>,
+    /// Number of queue entries fetched per page. The selected entries are read in a single page
+    /// if not set.
+    #[clap(long)]
+    pub page_size: Option<u64>,
+    /// Maximum number of pages fetched concurrently.
+    #[clap(long, default_value_t = 4)]
+    pub concurrency: usize,
+    /// List the newest forced inclusions first.
+    #[clap(long)]
+    pub reverse: bool,
+    /// Address of the Multicall3 contract batching the queue reads on Pacaya. The reads are
+    /// batched in JSON-RPC batch requests if it isn't deployed.
+    #[clap(long, env, default_value_t = MULTICALL3_ADDRESS)]
+    pub multicall_address: Address,
+    /// Filters of the listed forced inclusions.
+    #[clap(flatten)]
+    pub filter: QueueFilterOptions,
+    /// Read the queue at this L1 block: a number, a hash, or a tag such as `finalized`.
+    /// Reading old blocks requires an archive node.
+    #[clap(long)]
+    pub block: Option<BlockId>,
+    /// Save the whole queue to this JSON file, to be compared later with `diff-queue`.
+    #[clap(long, conflicts_with_all = [
+        "start", "limit", "blob_hash", "min_fee_gwei", "max_fee_gwei", "min_created_at",
+        "max_created_at",
+    ])]
+    pub snapshot: Option<PathBuf>,
+}
+
+impl Default for ReadQueueOptions {
+    fn default() -> Self {
+        Self {
+            start: None,
+            limit: None,
+            page_size: None,
+            concurrency: 4,
+            reverse: false,
+            multicall_address: MULTICALL3_ADDRESS,
+            filter: QueueFilterOptions::default(),
+            block: None,
+            snapshot: None,
+        }
+    }
+}
+
+/// Options for the diff-queue command.
+#[derive(Debug, Clone, Args)]
+pub struct DiffQueueOptions {
+    /// The older queue snapshot.
+    pub before: PathBuf,
+    /// The newer queue snapshot.
+    pub after: PathBuf,
+}
+
+/// Filters of the forced inclusions listed by read-queue. All the set filters must match.
+#[derive(Debug, Clone, Default, Args)]
+pub struct QueueFilterOptions {
+    /// Only list the forced inclusions with this blob hash.
+    #[clap(long)]
+    pub blob_hash: Option<B256>,
+    /// Only list the forced inclusions which paid at least this fee, in gwei.
+    #[clap(long)]
+    pub min_fee_gwei: Option<u64>,
+    /// Only list the forced inclusions which paid at most this fee, in gwei.
+    #[clap(long)]
+    pub max_fee_gwei: Option<u64>,
+    /// Only list the forced inclusions created at or after this point: a batch ID on Pacaya,
+    /// a timestamp on Shasta.
+    #[clap(long)]
+    pub min_created_at: Option<u64>,
+    /// Only list the forced inclusions created at or before this point: a batch ID on Pacaya,
+    /// a timestamp on Shasta.
+    #[clap(long)]
+    pub max_created_at: Option<u64>,
+}
+
+/// Blob sidecar format attached to the L1 forced inclusion transaction.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlobSidecarVersion {
+    /// Detect the format from the active L1 fork.
+    #[default]
+    Auto,
+    /// EIP-4844 sidecar with one KZG proof per blob (pre-Fusaka).
+    Eip4844,
+    /// EIP-7594 (PeerDAS) sidecar with cell proofs (post-Fusaka).
+    Eip7594,
 }
 
 /// Options for the send command.
-#[derive(Debug, Clone, Copy, Default, Parser)]
+#[derive(Debug, Clone, Default, Parser)]
 pub struct SendCmdOptions {
     /// The nonce delta to use for the forced inclusion transactions.
     ///
@@ -59,12 +306,244 @@ pub struct SendCmdOptions {
     /// from the same account.
     #[clap(long, default_value_t = 0)]
     pub nonce_delta: u64,
+    /// The blob sidecar format to use for the L1 transaction.
+    #[clap(long, env, value_enum, default_value_t = BlobSidecarVersion::Auto)]
+    pub blob_sidecar_version: BlobSidecarVersion,
+    /// Fee caps above which the forced inclusion is not sent.
+    #[clap(flatten)]
+    pub fee_caps: FeeCapOptions,
+    /// Fee bumping options for stuck L1 transactions.
+    #[clap(flatten)]
+    pub fee_bump: FeeBumpOptions,
+    /// Maximum time to wait for the L1 transaction to be mined, in seconds.
+    #[clap(long, env)]
+    pub receipt_timeout_secs: Option<u64>,
+    /// Wait until the forced inclusion fee plus the L1 blob fee falls below this amount,
+    /// in gwei, before sending.
+    #[clap(long)]
+    pub wait_for_fee: Option<u64>,
+    /// Maximum time to wait for the fee target, in seconds. The forced inclusion is sent
+    /// anyway once it expires, unless it exceeds the fee caps.
+    #[clap(long, default_value_t = 3600)]
+    pub wait_timeout_secs: u64,
+    /// The interval in seconds between fee checks while waiting for the fee target.
+    #[clap(long, default_value_t = 12)]
+    pub fee_poll_interval_secs: u64,
+    /// Run the pre-flight checks and estimate the cost without sending the transaction.
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Wait for the force-included L2 transaction to be included on L2, after the forced
+    /// inclusion is saved. Needs the L2 RPC.
+    #[clap(long, conflicts_with = "dry_run")]
+    pub wait_l2: bool,
+    /// Maximum time to wait for the L2 inclusion, in seconds.
+    #[clap(long, default_value_t = 3600)]
+    pub wait_l2_timeout_secs: u64,
+    /// A pre-signed, EIP-2718 encoded L2 transaction to force-include, instead of generating
+    /// a transfer with the L2 signer. No L2 signer is needed when set.
+    #[clap(long, conflicts_with = "nonce_delta")]
+    pub l2_raw_tx: Option<Bytes>,
+    /// The nonce of the L2 transaction, set by callers that track it themselves.
+    #[clap(skip)]
+    pub l2_nonce: Option<u64>,
 }
 
 /// Options for the spam command.
-#[derive(Debug, Clone, Copy, Default, Parser)]
+#[derive(Debug, Clone, Default, Parser)]
 pub struct SpamCmdOptions {
+    /// Load profile setting when forced inclusions are sent.
+    #[clap(flatten)]
+    pub load: LoadProfileOptions,
+    /// The blob sidecar format to use for the L1 transactions.
+    #[clap(long, env, value_enum, default_value_t = BlobSidecarVersion::Auto)]
+    pub blob_sidecar_version: BlobSidecarVersion,
+    /// Fee caps above which a forced inclusion is skipped until the next interval.
+    #[clap(flatten)]
+    pub fee_caps: FeeCapOptions,
+    /// Fee bumping options for stuck L1 transactions.
+    #[clap(flatten)]
+    pub fee_bump: FeeBumpOptions,
+    /// Retry options for transient failures.
+    #[clap(flatten)]
+    pub retry: RetryOptions,
+    /// Maximum time to wait for each L1 transaction to be mined, in seconds.
+    #[clap(long, env)]
+    pub receipt_timeout_secs: Option<u64>,
+    /// Total amount of ETH that can be spent on forced inclusions, including L1 gas.
+    ///
+    /// Spam stops once the next forced inclusion would exceed the remaining budget. The
+    /// worst-case cost of each forced inclusion is reserved before it's sent, so that
+    /// concurrent accounts can't overshoot the budget.
+    #[clap(long, env, value_parser = parse_ether)]
+    pub budget_eth: Option<U256>,
+    /// File with one L1 private key per line, to send from many L1 signers concurrently.
+    #[clap(long, env)]
+    pub l1_keys_file: Option<PathBuf>,
+    /// File with one L2 private key per line, paired with the L1 signers in order.
+    #[clap(long, env)]
+    pub l2_keys_file: Option<PathBuf>,
+    /// Number of consecutive accounts to derive from the mnemonic for each layer, starting
+    /// at the L1 and L2 mnemonic indexes, if no keys file is set.
+    #[clap(long, env, default_value_t = 1)]
+    pub mnemonic_accounts: u32,
+}
+
+/// Spam load profile, setting when forced inclusions are sent across all spam accounts.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoadProfile {
+    /// One forced inclusion every `--interval-secs`.
+    #[default]
+    Constant,
+    /// A rate ramping linearly from one forced inclusion every `--interval-secs` to one every
+    /// `--ramp-to-interval-secs`, over `--ramp-duration-secs`.
+    Ramp,
+    /// Bursts of `--burst-size` forced inclusions every `--burst-every-blocks` L1 blocks.
+    Bursty,
+    /// Random arrivals, with exponentially distributed intervals averaging `--interval-secs`.
+    Poisson,
+    /// One forced inclusion every `--every-blocks` L1 blocks, aligned to new L1 heads rather
+    /// than to the wall clock.
+    Blocks,
+    /// Only send while the queue holds fewer than `--target-queue-size` forced inclusions,
+    /// checking it every `--every-blocks` L1 blocks.
+    FillQueue,
+}
+
+/// Options for the spam load profile.
+#[derive(Debug, Clone, Copy, Args)]
+pub struct LoadProfileOptions {
+    /// The load profile of the forced inclusions.
+    #[clap(long, value_enum, default_value_t = LoadProfile::Constant)]
+    pub profile: LoadProfile,
     /// The interval in seconds between forced inclusion transactions.
     #[clap(long, default_value_t = 24)]
     pub interval_secs: u64,
+    /// The interval in seconds between forced inclusions at the end of the ramp.
+    #[clap(long, default_value_t = 4)]
+    pub ramp_to_interval_secs: u64,
+    /// The duration of the ramp in seconds, after which the final rate is kept.
+    #[clap(long, default_value_t = 600)]
+    pub ramp_duration_secs: u64,
+    /// The number of forced inclusions sent in each burst.
+    #[clap(long, default_value_t = 4)]
+    pub burst_size: u64,
+    /// The number of L1 blocks between the start of two bursts.
+    #[clap(long, default_value_t = 8)]
+    pub burst_every_blocks: u64,
+    /// The number of L1 blocks between two forced inclusions, or two queue size checks with
+    /// the fill-queue profile.
+    #[clap(long, default_value_t = 2)]
+    pub every_blocks: u64,
+    /// The queue size to maintain with the fill-queue profile.
+    #[clap(long, default_value_t = 8)]
+    pub target_queue_size: u64,
+    /// Stop after attempting this many forced inclusions.
+    #[clap(long)]
+    pub count: Option<u64>,
+    /// Stop sending new forced inclusions after this many seconds.
+    #[clap(long)]
+    pub duration_secs: Option<u64>,
+}
+
+impl Default for LoadProfileOptions {
+    fn default() -> Self {
+        Self {
+            profile: LoadProfile::Constant,
+            interval_secs: 24,
+            ramp_to_interval_secs: 4,
+            ramp_duration_secs: 600,
+            burst_size: 4,
+            burst_every_blocks: 8,
+            every_blocks: 2,
+            target_queue_size: 8,
+            count: None,
+            duration_secs: None,
+        }
+    }
+}
+
+/// Options for the cancel command.
+#[derive(Debug, Clone, Copy, Parser)]
+pub struct CancelCmdOptions {
+    /// Percentage by which the fees of the stuck transactions are bumped.
+    ///
+    /// Fees are always bumped by at least 100%, as required to replace blob transactions, so
+    /// only higher values have an effect.
+    #[clap(long, default_value_t = 100)]
+    pub fee_bump_percent: u64,
+    /// The blob sidecar format to use for the cancellation transactions.
+    #[clap(long, env, value_enum, default_value_t = BlobSidecarVersion::Auto)]
+    pub blob_sidecar_version: BlobSidecarVersion,
+    /// Maximum time to wait for the cancelled nonces to be mined, in seconds.
+    #[clap(long, default_value_t = 120)]
+    pub timeout_secs: u64,
+}
+
+/// Caps on the fees paid by a forced inclusion transaction.
+#[derive(Debug, Clone, Copy, Default, Args)]
+pub struct FeeCapOptions {
+    /// Maximum forced inclusion fee, in gwei.
+    ///
+    /// On Shasta, this fee grows with the number of pending forced inclusions.
+    #[clap(long, env)]
+    pub max_inclusion_fee_gwei: Option<u64>,
+    /// Maximum L1 blob base fee, in wei.
+    #[clap(long, env)]
+    pub max_blob_fee: Option<u128>,
+    /// Maximum total cost of the transaction (inclusion fee, gas and blob gas), in ETH.
+    #[clap(long, env, value_parser = parse_ether)]
+    pub max_total_cost: Option<U256>,
+}
+
+/// Options for replacing stuck L1 transactions with bumped fees.
+#[derive(Debug, Clone, Copy, Args)]
+pub struct FeeBumpOptions {
+    /// Number of L1 blocks without inclusion after which the transaction is resubmitted
+    /// with bumped fees.
+    #[clap(long, env, default_value_t = 3)]
+    pub resubmit_after_blocks: u64,
+    /// Percentage by which fees are bumped on each resubmission.
+    ///
+    /// Fees are always bumped by at least 100%, as required to replace blob transactions, so
+    /// only higher values have an effect.
+    #[clap(long, env, default_value_t = 100)]
+    pub fee_bump_percent: u64,
+    /// Maximum number of resubmissions, after which we keep waiting for the last one.
+    #[clap(long, env, default_value_t = 5)]
+    pub max_fee_bumps: u64,
+}
+
+impl Default for FeeBumpOptions {
+    fn default() -> Self {
+        Self {
+            resubmit_after_blocks: 3,
+            fee_bump_percent: 100,
+            max_fee_bumps: 5,
+        }
+    }
+}
+
+/// Retry options for the spam loop, to survive transient RPC failures.
+#[derive(Debug, Clone, Copy, Args)]
+pub struct RetryOptions {
+    /// Maximum number of retries of a forced inclusion after a transient error, such as an
+    /// RPC timeout, a too low nonce or an underpriced replacement.
+    #[clap(long, env, default_value_t = 3)]
+    pub max_retries: u32,
+    /// Delay before the first retry in seconds, doubled on each following retry.
+    #[clap(long, env, default_value_t = 2)]
+    pub retry_backoff_secs: u64,
+    /// Stop spamming from an account after this many forced inclusions failed in a row.
+    #[clap(long, env, default_value_t = 5)]
+    pub max_consecutive_failures: u32,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            retry_backoff_secs: 2,
+            max_consecutive_failures: 5,
+        }
+    }
 }
@@ -0,0 +1,195 @@
+use alloy::{
+    contract::Error as ContractError,
+    providers::{PendingTransactionError, WatchTxError},
+    transports::TransportError,
+};
+
+use crate::{
+    cli::MissingConfigError, fees::FeeCapError, preflight::PreflightError, send::SendError,
+    signer::SignerError,
+};
+
+/// Process exit codes, so that scripts wrapping the toolbox can react to each failure class.
+///
+/// Invalid CLI arguments exit with [`ExitCode::Config`] as well, as that's the code used by
+/// `clap` for usage errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ExitCode {
+    /// Unclassified failure.
+    Failure = 1,
+    /// Invalid configuration, e.g. a malformed CLI argument or `.env` file.
+    Config = 2,
+    /// An RPC endpoint could not be reached.
+    RpcUnreachable = 3,
+    /// A signer can't afford the transaction.
+    InsufficientFunds = 4,
+    /// The forced inclusion store rejected or reverted the transaction.
+    ContractRevert = 5,
+    /// Timed out waiting for an L1 receipt or for the L2 inclusion.
+    Timeout = 6,
+    /// The fees exceeded the configured fee caps.
+    FeeCapExceeded = 7,
+}
+
+impl ExitCode {
+    /// Classifies an error into its exit code, by looking for known errors in its chain.
+    pub fn from_error(err: &eyre::Report) -> Self {
+        err.chain()
+            .find_map(|cause| {
+                if cause.is::<dotenvy::Error>()
+                    || cause.is::<SignerError>()
+                    || cause.is::<MissingConfigError>()
+                {
+                    Some(Self::Config)
+                } else if cause.is::<FeeCapError>() {
+                    Some(Self::FeeCapExceeded)
+                } else if let Some(e) = cause.downcast_ref::<PreflightError>() {
+                    Some(match e {
+                        PreflightError::InsufficientFunds { .. } => Self::InsufficientFunds,
+                        PreflightError::NoStoreContract(_) => Self::Config,
+                    })
+                } else if let Some(e) = cause.downcast_ref::<SendError>() {
+                    Self::from_send_error(e)
+                } else if let Some(e) = cause.downcast_ref::<ContractError>() {
+                    Self::from_contract_error(e)
+                } else if let Some(e) = cause.downcast_ref::<PendingTransactionError>() {
+                    Self::from_pending_tx_error(e)
+                } else if let Some(e) = cause.downcast_ref::<TransportError>() {
+                    Self::from_transport_error(e)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Self::Failure)
+    }
+
+    fn from_send_error(err: &SendError) -> Option<Self> {
+        match err {
+            SendError::FeeTooLow
+            | SendError::BlobNotFound
+            | SendError::InvalidParams
+            | SendError::Store(_)
+            | SendError::Reverted { .. } => Some(Self::ContractRevert),
+            SendError::ReceiptTimeout { .. }
+            | SendError::L2InclusionTimeout { .. }
+            | SendError::StuckPending { .. } => Some(Self::Timeout),
+            SendError::Contract(e) => Self::from_contract_error(e),
+            SendError::Rpc(e) => Self::from_transport_error(e),
+        }
+    }
+
+    fn from_contract_error(err: &ContractError) -> Option<Self> {
+        match err {
+            ContractError::TransportError(e) => Self::from_transport_error(e),
+            ContractError::PendingTransactionError(e) => Self::from_pending_tx_error(e),
+            _ => None,
+        }
+    }
+
+    fn from_pending_tx_error(err: &PendingTransactionError) -> Option<Self> {
+        match err {
+            PendingTransactionError::TxWatcher(WatchTxError::Timeout) => Some(Self::Timeout),
+            PendingTransactionError::TransportError(e) => Self::from_transport_error(e),
+            _ => None,
+        }
+    }
+
+    fn from_transport_error(err: &TransportError) -> Option<Self> {
+        if err.is_transport_error() {
+            return Some(Self::RpcUnreachable);
+        }
+
+        let payload = err.as_error_resp()?;
+        if payload.message.contains("insufficient funds") {
+            Some(Self::InsufficientFunds)
+        } else if payload.as_revert_data().is_some() || payload.message.contains("revert") {
+            Some(Self::ContractRevert)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        Self::from(code as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use alloy::{
+        primitives::{Address, B256, U256},
+        transports::{RpcError, TransportErrorKind},
+    };
+    use eyre::WrapErr;
+
+    use super::*;
+
+    fn classify(err: impl std::error::Error + Send + Sync + 'static) -> ExitCode {
+        ExitCode::from_error(&eyre::Report::new(err))
+    }
+
+    fn error_resp(message: &str) -> TransportError {
+        let payload = serde_json::json!({ "code": -32000, "message": message });
+        RpcError::ErrorResp(serde_json::from_value(payload).unwrap())
+    }
+
+    #[test]
+    fn classifies_each_failure() {
+        assert_eq!(
+            ExitCode::from_error(&eyre::eyre!("unexpected")),
+            ExitCode::Failure
+        );
+        assert_eq!(classify(MissingConfigError("--l2-rpc")), ExitCode::Config);
+        assert_eq!(
+            classify(TransportErrorKind::custom_str("connection refused")),
+            ExitCode::RpcUnreachable
+        );
+        assert_eq!(
+            classify(PreflightError::InsufficientFunds {
+                layer: "L1",
+                address: Address::ZERO,
+                balance: U256::ZERO,
+                required: U256::from(1),
+            }),
+            ExitCode::InsufficientFunds
+        );
+        assert_eq!(
+            classify(error_resp("insufficient funds for gas * price + value")),
+            ExitCode::InsufficientFunds
+        );
+        assert_eq!(classify(SendError::FeeTooLow), ExitCode::ContractRevert);
+        assert_eq!(
+            classify(error_resp("execution reverted")),
+            ExitCode::ContractRevert
+        );
+        assert_eq!(
+            classify(SendError::ReceiptTimeout {
+                tx_hash: B256::ZERO,
+                timeout: Duration::from_secs(60),
+            }),
+            ExitCode::Timeout
+        );
+        assert_eq!(
+            classify(FeeCapError::InclusionFee { fee: 2, cap: 1 }),
+            ExitCode::FeeCapExceeded
+        );
+    }
+
+    #[test]
+    fn classifies_wrapped_errors() {
+        let err = Err::<(), _>(SendError::FeeTooLow)
+            .wrap_err("failed to send the forced inclusion")
+            .unwrap_err();
+        assert_eq!(ExitCode::from_error(&err), ExitCode::ContractRevert);
+
+        let err = Err::<(), _>(error_resp("rate limited"))
+            .wrap_err("failed to read the queue")
+            .unwrap_err();
+        assert_eq!(ExitCode::from_error(&err), ExitCode::Failure);
+    }
+}
@@ -0,0 +1,243 @@
+use std::time::{Duration, Instant};
+
+use alloy::{
+    consensus::constants::GWEI_TO_WEI,
+    contract::{CallBuilder, CallDecoder},
+    eips::eip4844::DATA_GAS_PER_BLOB,
+    primitives::{U256, utils::format_ether},
+    providers::Provider,
+};
+
+use tokio::time::sleep;
+
+use crate::cli::FeeCapOptions;
+
+/// Buffer applied on top of the current blob base fee, in percent.
+const BLOB_FEE_BUFFER_PERCENT: u128 = 120;
+
+/// The minimum fee bump required to replace a blob transaction, in percent.
+const MIN_BLOB_FEE_BUMP_PERCENT: u64 = 100;
+
+/// The intrinsic gas of a transaction, a lower bound of any gas limit.
+const MIN_TRANSACTION_GAS: u64 = 21_000;
+
+/// Applies a 20% buffer to the given blob base fee, to ensure we're above the current fee.
+fn buffered_blob_fee(base_fee: u128) -> u128 {
+    base_fee.saturating_mul(BLOB_FEE_BUFFER_PERCENT) / 100
+}
+
+/// An error returned when a forced inclusion would exceed one of the configured fee caps.
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+pub enum FeeCapError {
+    #[error("forced inclusion fee of {fee} gwei exceeds the cap of {cap} gwei")]
+    InclusionFee { fee: u64, cap: u64 },
+    #[error("blob base fee of {fee} wei exceeds the cap of {cap} wei")]
+    BlobFee { fee: u128, cap: u128 },
+    #[error("total cost of {} ETH exceeds the cap of {} ETH", format_ether(*.cost), format_ether(*.cap))]
+    TotalCost { cost: U256, cap: U256 },
+    #[error("cost of {} ETH exceeds the remaining spam budget of {} ETH", format_ether(*.cost), format_ether(*.remaining))]
+    Budget { cost: U256, remaining: U256 },
+}
+
+/// The fees of a forced inclusion L1 transaction, and the worst-case cost they add up to.
+#[derive(Debug, Clone, Copy)]
+pub struct InclusionCost {
+    /// The forced inclusion fee paid to the store, in gwei.
+    pub inclusion_fee_gwei: u64,
+    /// The current blob base fee, in wei.
+    pub blob_base_fee: u128,
+    /// The max fee per blob gas set on the transaction, in wei.
+    pub max_fee_per_blob_gas: u128,
+    /// The blob gas used by the transaction sidecar.
+    pub blob_gas: u64,
+    /// The max fee per gas set on the transaction, in wei.
+    pub max_fee_per_gas: u128,
+    /// The max priority fee per gas set on the transaction, in wei.
+    pub max_priority_fee_per_gas: u128,
+    /// The gas limit of the transaction.
+    pub gas_limit: u64,
+}
+
+impl InclusionCost {
+    /// Fetches the current L1 fees for a forced inclusion with `num_blobs` blobs.
+    ///
+    /// The gas limit is set to the intrinsic gas of a transaction until [`Self::estimate_gas`]
+    /// is called, so that [`Self::total`] is a lower bound of the cost.
+    pub async fn fetch<P: Provider>(
+        provider: &P,
+        inclusion_fee_gwei: u64,
+        num_blobs: usize,
+    ) -> eyre::Result<Self> {
+        let fees = provider.estimate_eip1559_fees().await?;
+        let blob_base_fee = provider.get_blob_base_fee().await?;
+
+        Ok(Self {
+            inclusion_fee_gwei,
+            blob_base_fee,
+            max_fee_per_blob_gas: buffered_blob_fee(blob_base_fee),
+            blob_gas: num_blobs as u64 * DATA_GAS_PER_BLOB,
+            max_fee_per_gas: fees.max_fee_per_gas,
+            max_priority_fee_per_gas: fees.max_priority_fee_per_gas,
+            gas_limit: MIN_TRANSACTION_GAS,
+        })
+    }
+
+    /// Estimates the gas limit of the given forced inclusion call.
+    ///
+    /// The estimate fails with a bare RPC error if the sender can't afford the inclusion fee,
+    /// so the balance should be checked against [`Self::total`] beforehand.
+    pub async fn estimate_gas<P: Provider, D: CallDecoder>(
+        self,
+        call: &CallBuilder<P, D>,
+    ) -> eyre::Result<Self> {
+        let gas_limit = call.estimate_gas().await?;
+        Ok(Self { gas_limit, ..self })
+    }
+
+    /// The forced inclusion fee, in wei.
+    pub fn inclusion_fee(&self) -> U256 {
+        U256::from(self.inclusion_fee_gwei) * U256::from(GWEI_TO_WEI)
+    }
+
+    /// The maximum amount of wei the transaction can cost, including the inclusion fee.
+    pub fn total(&self) -> U256 {
+        self.inclusion_fee()
+            + U256::from(self.gas_limit) * U256::from(self.max_fee_per_gas)
+            + U256::from(self.blob_gas) * U256::from(self.max_fee_per_blob_gas)
+    }
+
+    /// Checks the cost against the given fee caps.
+    pub fn check(&self, caps: &FeeCapOptions) -> Result<(), FeeCapError> {
+        if let Some(cap) = caps.max_inclusion_fee_gwei
+            && self.inclusion_fee_gwei > cap
+        {
+            return Err(FeeCapError::InclusionFee {
+                fee: self.inclusion_fee_gwei,
+                cap,
+            });
+        }
+
+        if let Some(cap) = caps.max_blob_fee
+            && self.blob_base_fee > cap
+        {
+            return Err(FeeCapError::BlobFee {
+                fee: self.blob_base_fee,
+                cap,
+            });
+        }
+
+        if let Some(cap) = caps.max_total_cost
+            && self.total() > cap
+        {
+            return Err(FeeCapError::TotalCost {
+                cost: self.total(),
+                cap,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the cost with all fees bumped by `percent`, for replacing a stuck transaction.
+    ///
+    /// Fees are bumped by at least 100%, as required to replace blob transactions: the tip,
+    /// the max fee and the blob fee must all be doubled.
+    pub fn bumped(&self, percent: u64) -> Self {
+        let percent = u128::from(percent.max(MIN_BLOB_FEE_BUMP_PERCENT));
+        let bump = |fee: u128| fee.saturating_mul(100 + percent) / 100;
+        Self {
+            max_fee_per_gas: bump(self.max_fee_per_gas),
+            max_priority_fee_per_gas: bump(self.max_priority_fee_per_gas),
+            // Bump from at least 1 wei, in case the blob base fee is zero.
+            max_fee_per_blob_gas: bump(self.max_fee_per_blob_gas.max(1)),
+            ..*self
+        }
+    }
+
+    /// Pins the estimated fees and gas limit on the call, so that the transaction can't
+    /// cost more than what was checked against the caps.
+    pub fn apply<P: Provider, D: CallDecoder>(&self, call: CallBuilder<P, D>) -> CallBuilder<P, D> {
+        call.gas(self.gas_limit)
+            .max_fee_per_gas(self.max_fee_per_gas)
+            .max_priority_fee_per_gas(self.max_priority_fee_per_gas)
+            .max_fee_per_blob_gas(self.max_fee_per_blob_gas)
+    }
+}
+
+/// Polls the forced inclusion fee and the L1 blob base fee until their combined cost for
+/// a single-blob forced inclusion falls below `target_gwei`, or `timeout` expires.
+///
+/// The forced inclusion fee is read with `inclusion_fee_gwei`, as it's exposed differently
+/// by each fork. Returns how long it waited.
+pub async fn wait_for_fee<P, F, Fut>(
+    l1: &P,
+    target_gwei: u64,
+    timeout: Duration,
+    poll_interval: Duration,
+    mut inclusion_fee_gwei: F,
+) -> eyre::Result<Duration>
+where
+    P: Provider,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = eyre::Result<u64>>,
+{
+    let start = Instant::now();
+    loop {
+        let fee_gwei = inclusion_fee_gwei().await?;
+        let blob_base_fee = l1.get_blob_base_fee().await?;
+        let blob_fee_gwei = blob_base_fee * DATA_GAS_PER_BLOB as u128 / GWEI_TO_WEI as u128;
+        let combined_gwei = fee_gwei as u128 + blob_fee_gwei;
+
+        let waited = start.elapsed();
+        if combined_gwei <= target_gwei as u128 {
+            println!(
+                "✅ Combined fee of {combined_gwei} gwei is below the target of {target_gwei} gwei after waiting {}s",
+                waited.as_secs()
+            );
+            return Ok(waited);
+        }
+
+        if waited >= timeout {
+            println!(
+                "⌛ Combined fee of {combined_gwei} gwei is still above the target of {target_gwei} gwei after waiting {}s, sending anyway",
+                waited.as_secs()
+            );
+            return Ok(waited);
+        }
+
+        println!(
+            "⏳ Combined fee of {combined_gwei} gwei (inclusion: {fee_gwei} gwei, blob: {blob_fee_gwei} gwei) is above the target of {target_gwei} gwei, waiting..."
+        );
+        sleep(poll_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cost() -> InclusionCost {
+        InclusionCost {
+            inclusion_fee_gwei: 1,
+            blob_base_fee: 0,
+            max_fee_per_blob_gas: 0,
+            blob_gas: DATA_GAS_PER_BLOB,
+            max_fee_per_gas: 1_000,
+            max_priority_fee_per_gas: 100,
+            gas_limit: 100_000,
+        }
+    }
+
+    #[test]
+    fn bumps_all_fees_by_at_least_100_percent() {
+        let bumped = cost().bumped(10);
+        assert_eq!(bumped.max_fee_per_gas, 2_000);
+        assert_eq!(bumped.max_priority_fee_per_gas, 200);
+        assert_eq!(bumped.max_fee_per_blob_gas, 2);
+
+        let bumped = cost().bumped(150);
+        assert_eq!(bumped.max_fee_per_gas, 2_500);
+        assert_eq!(bumped.max_priority_fee_per_gas, 250);
+    }
+}
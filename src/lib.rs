@@ -0,0 +1,37 @@
+//! Toolbox to interact with the Taiko `ForcedInclusionStore` contract.
+
+#![cfg_attr(not(test), warn(unused_crate_dependencies))]
+#![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
+
+pub mod blob;
+
+pub mod cancel;
+
+pub mod cli;
+
+pub mod exit_code;
+
+pub mod fees;
+
+pub mod pacaya;
+pub mod shasta;
+
+pub mod preflight;
+
+pub mod queue;
+
+pub mod remote_signer;
+
+pub mod retry;
+
+pub mod schedule;
+
+pub mod send;
+
+pub mod signer;
+
+pub mod spam;
+
+pub mod state;
+
+pub mod wallet_provider;
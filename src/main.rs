@@ -1,33 +1,34 @@
-#![cfg_attr(not(test), warn(unused_crate_dependencies))]
-#![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
-
 use clap::Parser;
 
-mod blob;
-
-mod cli;
-use cli::{Cli, Fork};
-
-mod pacaya;
-mod shasta;
-
-mod wallet_provider;
-
-#[tokio::main]
-async fn main() -> eyre::Result<()> {
-    if let Ok(custom_env_file) = std::env::var("ENV_FILE") {
-        // Try from custom env file, and abort if it fails
-        dotenvy::from_filename(custom_env_file)?;
-    } else {
-        // Try from default .env file, and ignore if it fails. It might
-        // be that the user isn't using it.
-        dotenvy::dotenv().ok();
+use taiko_forced_inclusion_toolbox::{
+    cli::{Cli, Fork, load_env},
+    exit_code::ExitCode,
+    pacaya, shasta,
+};
+
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from_error(&err).into()
+        }
     }
+}
+
+fn run() -> eyre::Result<()> {
+    // Load the environment before starting the runtime, while this is the only thread.
+    load_env()?;
 
     let cli = Cli::parse();
 
-    match &cli.fork {
-        Fork::Pacaya => pacaya::handle_command(cli).await,
-        Fork::Shasta => shasta::handle_command(cli).await,
-    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        match &cli.fork {
+            Fork::Pacaya => pacaya::handle_command(cli).await,
+            Fork::Shasta => shasta::handle_command(cli).await,
+        }
+    })
 }
@@ -0,0 +1,440 @@
+use std::ops::Range;
+
+use alloy::{
+    eips::BlockId,
+    primitives::{Address, Bytes, U256},
+    providers::{MulticallItem, Provider, bindings::IMulticall3},
+    rpc::{client::BatchRequest, types::TransactionRequest},
+    sol_types::{SolCall, SolInterface},
+    transports::TransportError,
+};
+
+use super::chainio::IForcedInclusionStore::{
+    ForcedInclusion, IForcedInclusionStoreErrors, IForcedInclusionStoreInstance, feeInGweiCall,
+    getForcedInclusionCall, getOldestForcedInclusionDeadlineCall, headCall, inclusionDelayCall,
+    isOldestForcedInclusionDueCall, tailCall,
+};
+
+/// The queue pointers and parameters of the store.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueState {
+    pub head: u64,
+    pub tail: u64,
+    pub fee_in_gwei: u64,
+    pub inclusion_delay: u8,
+    pub oldest_due: bool,
+    /// The batch ID from which the queue head is due, or `U256::MAX` if the queue is empty.
+    pub oldest_deadline: U256,
+}
+
+/// The queue state and the forced inclusions read along with it, in a single round trip.
+#[derive(Debug)]
+pub struct QueueRead {
+    pub state: QueueState,
+    /// The forced inclusions by queue index, or the error reading each of them.
+    pub forced_inclusions: Vec<(u64, eyre::Result<ForcedInclusion>)>,
+}
+
+/// Reads the forced inclusion store in batches: through the Multicall3 contract if it's
+/// deployed, or else in JSON-RPC batch requests, as the store only returns one forced inclusion
+/// per call.
+#[derive(Debug)]
+pub struct BatchReader<'a, P> {
+    store: &'a IForcedInclusionStoreInstance<P>,
+    /// The Multicall3 contract, if it's deployed.
+    multicall: Option<Address>,
+    /// The block at which the store is read.
+    block: BlockId,
+}
+
+impl<'a, P: Provider> BatchReader<'a, P> {
+    /// Builds a reader of `store` at `block`, batching the reads through the Multicall3 contract
+    /// at `multicall_address` if it's deployed at that block.
+    pub async fn new(
+        store: &'a IForcedInclusionStoreInstance<P>,
+        multicall_address: Address,
+        block: BlockId,
+    ) -> eyre::Result<Self> {
+        let code = store
+            .provider()
+            .get_code_at(multicall_address)
+            .block_id(block)
+            .await?;
+        let multicall = if code.is_empty() {
+            println!(
+                "⚠️ No Multicall3 contract at {multicall_address}, batching the queue reads in JSON-RPC batch requests instead"
+            );
+            None
+        } else {
+            Some(multicall_address)
+        };
+
+        Ok(Self {
+            store,
+            multicall,
+            block,
+        })
+    }
+
+    /// Reads the queue pointers and parameters of the store, and the forced inclusions of the
+    /// queue indices in `range`, in a single call to the Multicall3 contract, or else in a
+    /// single JSON-RPC batch request.
+    pub async fn read(&self, range: Range<u64>) -> eyre::Result<QueueRead> {
+        let store = self.store;
+        let state_calls = [
+            store.head().input(),
+            store.tail().input(),
+            store.feeInGwei().input(),
+            store.inclusionDelay().input(),
+            store.isOldestForcedInclusionDue().input(),
+            store.getOldestForcedInclusionDeadline().input(),
+        ];
+        let fi_calls: Vec<_> = range
+            .clone()
+            .map(|i| store.getForcedInclusion(U256::from(i)).input())
+            .collect();
+
+        if let Some(address) = self.multicall {
+            match self.aggregate3(address, &state_calls, &fi_calls).await {
+                Ok(results) => {
+                    let (state, fis) = results.split_at(state_calls.len());
+                    let state: Vec<_> = state.iter().map(|res| res.returnData.clone()).collect();
+                    let fis = fis.iter().map(|res| match res.success {
+                        true => decode::<getForcedInclusionCall>(&res.returnData),
+                        false => Err(multicall_error(&res.returnData)),
+                    });
+                    return Ok(QueueRead {
+                        state: decode_state(&state)?,
+                        forced_inclusions: range.zip(fis).collect(),
+                    });
+                }
+                Err(e) => println!("⚠️ Multicall failed, retrying in a JSON-RPC batch: {e}"),
+            }
+        }
+
+        let mut batch = BatchRequest::new(store.provider().client());
+        let mut add_call = |input: &Bytes| {
+            let tx = TransactionRequest::default()
+                .to(*store.address())
+                .input(input.clone().into());
+            batch.add_call::<_, Bytes>("eth_call", &(tx, self.block))
+        };
+        let state_waiters = state_calls
+            .iter()
+            .map(&mut add_call)
+            .collect::<Result<Vec<_>, _>>()?;
+        let fi_waiters = fi_calls
+            .iter()
+            .map(&mut add_call)
+            .collect::<Result<Vec<_>, _>>()?;
+        batch.send().await?;
+
+        let mut state = Vec::with_capacity(state_waiters.len());
+        for waiter in state_waiters {
+            state.push(waiter.await?);
+        }
+        let mut forced_inclusions = Vec::with_capacity(fi_waiters.len());
+        for (i, waiter) in range.zip(fi_waiters) {
+            let fi = match waiter.await {
+                Ok(data) => decode::<getForcedInclusionCall>(&data),
+                Err(e) => Err(rpc_error(e)),
+            };
+            forced_inclusions.push((i, fi));
+        }
+        Ok(QueueRead {
+            state: decode_state(&state)?,
+            forced_inclusions,
+        })
+    }
+
+    /// Calls the store with `state_calls`, which must succeed, and `fi_calls`, which may fail,
+    /// through the `aggregate3` function of the Multicall3 contract at `address`.
+    async fn aggregate3(
+        &self,
+        address: Address,
+        state_calls: &[Bytes],
+        fi_calls: &[Bytes],
+    ) -> eyre::Result<Vec<IMulticall3::Result>> {
+        let call3 = |allow_failure| {
+            move |input: &Bytes| IMulticall3::Call3 {
+                target: *self.store.address(),
+                allowFailure: allow_failure,
+                callData: input.clone(),
+            }
+        };
+        let calls = state_calls
+            .iter()
+            .map(call3(false))
+            .chain(fi_calls.iter().map(call3(true)))
+            .collect();
+
+        let tx = TransactionRequest::default()
+            .to(address)
+            .input(IMulticall3::aggregate3Call { calls }.abi_encode().into());
+        let data = self.store.provider().call(tx).block(self.block).await?;
+        Ok(IMulticall3::aggregate3Call::abi_decode_returns(&data)?)
+    }
+}
+
+/// Decodes the queue state from the return data of its calls, in the order of its fields.
+fn decode_state(data: &[Bytes]) -> eyre::Result<QueueState> {
+    Ok(QueueState {
+        head: decode::<headCall>(&data[0])?,
+        tail: decode::<tailCall>(&data[1])?,
+        fee_in_gwei: decode::<feeInGweiCall>(&data[2])?,
+        inclusion_delay: decode::<inclusionDelayCall>(&data[3])?,
+        oldest_due: decode::<isOldestForcedInclusionDueCall>(&data[4])?,
+        oldest_deadline: decode::<getOldestForcedInclusionDeadlineCall>(&data[5])?,
+    })
+}
+
+/// Decodes the return data of a batched `eth_call` of `C`.
+fn decode<C: SolCall>(data: &Bytes) -> eyre::Result<C::Return> {
+    Ok(C::abi_decode_returns(data)?)
+}
+
+/// Describes a forced inclusion read that failed in a multicall, from its revert data.
+fn multicall_error(return_data: &Bytes) -> eyre::Report {
+    match IForcedInclusionStoreErrors::abi_decode(return_data) {
+        Ok(err) => eyre::eyre!("{err:?}"),
+        Err(_) => eyre::eyre!("call reverted with {return_data}"),
+    }
+}
+
+/// Describes a forced inclusion read that failed in a JSON-RPC batch, decoding the store
+/// error from the error response if possible.
+fn rpc_error(err: TransportError) -> eyre::Report {
+    match err
+        .as_error_resp()
+        .and_then(|payload| payload.as_decoded_interface_error::<IForcedInclusionStoreErrors>())
+    {
+        Some(err) => eyre::eyre!("{err:?}"),
+        None => eyre::eyre!("{err:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use alloy::{
+        primitives::{B256, address, hex},
+        providers::ProviderBuilder,
+        sol_types::SolError,
+    };
+    use axum::{Json, Router, extract::State, routing::post};
+    use serde_json::{Value, json};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::pacaya::chainio::IForcedInclusionStore::{
+        self, IForcedInclusionStoreCalls, InvalidIndex,
+    };
+
+    const STORE: Address = address!("0x00000000000000000000000000000000000f1f1f");
+    const MULTICALL: Address = address!("0x00000000000000000000000000000000000ca11a");
+
+    /// The index of the forced inclusion the stand-in store fails to read.
+    const MISSING_INDEX: u64 = 4;
+
+    #[derive(Default)]
+    struct Node {
+        multicall_deployed: bool,
+        multicalls: AtomicUsize,
+    }
+
+    /// Executes a call to the stand-in store, whose queue holds the indices [2, 6).
+    fn store_call(input: &[u8]) -> Result<Vec<u8>, Vec<u8>> {
+        Ok(
+            match IForcedInclusionStoreCalls::abi_decode(input).unwrap() {
+                IForcedInclusionStoreCalls::head(_) => headCall::abi_encode_returns(&2),
+                IForcedInclusionStoreCalls::tail(_) => tailCall::abi_encode_returns(&6),
+                IForcedInclusionStoreCalls::feeInGwei(_) => {
+                    feeInGweiCall::abi_encode_returns(&1000)
+                }
+                IForcedInclusionStoreCalls::inclusionDelay(_) => {
+                    inclusionDelayCall::abi_encode_returns(&12)
+                }
+                IForcedInclusionStoreCalls::isOldestForcedInclusionDue(_) => {
+                    isOldestForcedInclusionDueCall::abi_encode_returns(&true)
+                }
+                IForcedInclusionStoreCalls::getOldestForcedInclusionDeadline(_) => {
+                    getOldestForcedInclusionDeadlineCall::abi_encode_returns(&U256::from(110))
+                }
+                IForcedInclusionStoreCalls::getForcedInclusion(call) => {
+                    let index = call.index.to::<u64>();
+                    if index == MISSING_INDEX {
+                        return Err(InvalidIndex {}.abi_encode());
+                    }
+                    let fi = ForcedInclusion {
+                        blobHash: B256::with_last_byte(index as u8),
+                        feeInGwei: index,
+                        createdAtBatchId: 100 + index,
+                        blobByteOffset: 0,
+                        blobByteSize: 1024,
+                        blobCreatedIn: 1000 + index,
+                    };
+                    getForcedInclusionCall::abi_encode_returns(&fi)
+                }
+                call => panic!("unexpected store call {call:?}"),
+            },
+        )
+    }
+
+    /// Executes an `eth_call` to the stand-in store or Multicall3 contract.
+    fn eth_call(node: &Node, to: Address, input: &[u8]) -> Result<Vec<u8>, Vec<u8>> {
+        if to == STORE {
+            return store_call(input);
+        }
+        assert_eq!(to, MULTICALL, "unexpected call to {to}");
+        node.multicalls.fetch_add(1, Ordering::Relaxed);
+
+        let calls = IMulticall3::aggregate3Call::abi_decode(input)
+            .unwrap()
+            .calls;
+        let results: Vec<_> = calls
+            .iter()
+            .map(|call| {
+                assert_eq!(call.target, STORE);
+                let (success, data) = match store_call(&call.callData) {
+                    Ok(data) => (true, data),
+                    Err(data) => (false, data),
+                };
+                IMulticall3::Result {
+                    success,
+                    returnData: data.into(),
+                }
+            })
+            .collect();
+        Ok(IMulticall3::aggregate3Call::abi_encode_returns(&results))
+    }
+
+    /// Answers a single JSON-RPC request.
+    fn handle(node: &Node, req: &Value) -> Value {
+        let id = &req["id"];
+        let params = &req["params"];
+        match req["method"].as_str().unwrap() {
+            "eth_getCode" => {
+                let code = if node.multicall_deployed {
+                    "0x01"
+                } else {
+                    "0x"
+                };
+                json!({ "jsonrpc": "2.0", "id": id, "result": code })
+            }
+            "eth_call" => {
+                let tx = &params[0];
+                let to = tx["to"].as_str().unwrap().parse().unwrap();
+                let input = tx.get("input").or_else(|| tx.get("data")).unwrap();
+                let input = hex::decode(input.as_str().unwrap()).unwrap();
+                match eth_call(node, to, &input) {
+                    Ok(data) => {
+                        json!({ "jsonrpc": "2.0", "id": id, "result": hex::encode_prefixed(data) })
+                    }
+                    Err(data) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": 3,
+                            "message": "execution reverted",
+                            "data": hex::encode_prefixed(data),
+                        },
+                    }),
+                }
+            }
+            method => panic!("unexpected method {method}"),
+        }
+    }
+
+    /// Serves a stand-in L1 node holding the forced inclusion store, and returns a reader of
+    /// the store through it.
+    async fn serve_node(node: Arc<Node>) -> IForcedInclusionStoreInstance<impl Provider> {
+        async fn rpc(State(node): State<Arc<Node>>, Json(req): Json<Value>) -> Json<Value> {
+            Json(match req.as_array() {
+                Some(batch) => batch.iter().map(|req| handle(&node, req)).collect(),
+                None => handle(&node, &req),
+            })
+        }
+
+        let app = Router::new().route("/", post(rpc)).with_state(node);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let url = format!("http://{addr}").parse().unwrap();
+        IForcedInclusionStore::new(STORE, ProviderBuilder::new().connect_http(url))
+    }
+
+    async fn check_reads(reader: &BatchReader<'_, impl Provider>) {
+        let QueueRead {
+            state,
+            forced_inclusions,
+        } = reader.read(2..6).await.unwrap();
+        assert_eq!((state.head, state.tail), (2, 6));
+        assert_eq!((state.fee_in_gwei, state.inclusion_delay), (1000, 12));
+        assert!(state.oldest_due);
+        assert_eq!(state.oldest_deadline, U256::from(110));
+
+        let indices: Vec<_> = forced_inclusions.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, [2, 3, 4, 5]);
+        for (i, fi) in forced_inclusions {
+            match fi {
+                Ok(fi) => assert_eq!(fi.createdAtBatchId, 100 + i),
+                Err(e) => {
+                    assert_eq!(i, MISSING_INDEX);
+                    assert!(e.to_string().contains("InvalidIndex"), "{e}");
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_json_rpc_batches_without_multicall() {
+        let node = Arc::new(Node::default());
+        let store = serve_node(node.clone()).await;
+
+        let reader = BatchReader::new(&store, MULTICALL, BlockId::latest())
+            .await
+            .unwrap();
+        assert!(reader.multicall.is_none());
+        check_reads(&reader).await;
+        assert_eq!(node.multicalls.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn reads_through_multicall() {
+        let node = Arc::new(Node {
+            multicall_deployed: true,
+            ..Default::default()
+        });
+        let store = serve_node(node.clone()).await;
+
+        let reader = BatchReader::new(&store, MULTICALL, BlockId::latest())
+            .await
+            .unwrap();
+        assert_eq!(reader.multicall, Some(MULTICALL));
+        check_reads(&reader).await;
+        assert_eq!(node.multicalls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn describes_failed_reads() {
+        let err = multicall_error(&InvalidIndex {}.abi_encode().into());
+        assert_eq!(err.to_string(), "InvalidIndex(InvalidIndex)");
+        let err = multicall_error(&Bytes::from_static(&[0xde, 0xad]));
+        assert_eq!(err.to_string(), "call reverted with 0xdead");
+
+        let payload = json!({
+            "code": 3,
+            "message": "execution reverted",
+            "data": hex::encode_prefixed(InvalidIndex {}.abi_encode()),
+        });
+        let err = rpc_error(TransportError::ErrorResp(
+            serde_json::from_value(payload).unwrap(),
+        ));
+        assert_eq!(err.to_string(), "InvalidIndex(InvalidIndex)");
+    }
+}
@@ -6,10 +6,12 @@ sol! {
     interface IForcedInclusionStore {
         uint8 public immutable inclusionDelay;
         uint64 public immutable feeInGwei;
+        address public immutable inbox;
 
         uint64 public head;
         uint64 public tail;
 
+        #[derive(serde::Serialize, serde::Deserialize)]
         struct ForcedInclusion {
             bytes32 blobHash;
             uint64 feeInGwei;
@@ -72,4 +74,20 @@ sol! {
             external
             payable;
     }
+
+    #[sol(rpc)]
+    #[derive(Debug)]
+    interface ITaikoInbox {
+        struct Stats2 {
+            uint64 numBatches;
+            uint64 lastVerifiedBatchId;
+            bool paused;
+            uint56 lastProposedIn;
+            uint64 lastUnpausedAt;
+        }
+
+        /// @notice Retrieves the second set of protocol statistics.
+        /// @return Stats2 structure containing the statistics.
+        function getStats2() external view returns (Stats2 memory);
+    }
 }
@@ -1,151 +1,540 @@
+mod batch;
 mod chainio;
 
-use std::{io::Write, time::Duration};
+use std::{fmt, io::Write, ops::Range, time::Duration};
 
 use alloy::{
     consensus::{Transaction, constants::GWEI_TO_WEI},
-    network::TransactionBuilder,
-    primitives::{Address, Bytes, U256},
-    providers::{Provider, ProviderBuilder, WalletProvider},
-    rpc::types::TransactionRequest,
+    eips::{BlockId, eip7594::BlobTransactionSidecarVariant},
+    primitives::{Address, B256, Bytes, U256, utils::format_ether},
+    providers::{Provider, WalletProvider},
+    rpc::types::TransactionReceipt,
+    sol_types::SolEvent,
 };
+use eyre::WrapErr;
 use flate2::{Compression, write::ZlibEncoder};
 use futures::StreamExt;
-use tokio::time::sleep;
 
 use crate::{
-    blob::create_blob_sidecar_from_data_async,
+    blob::{BlobEncoder, resolve_sidecar_version},
+    cancel::cancel_pending,
     cli::{
-        Cmd::{MonitorQueue, ReadQueue, Send, Spam},
-        SendCmdOptions, SpamCmdOptions,
+        Cmd::{Cancel, DiffQueue, MonitorQueue, Preflight, ReadQueue, Send, Spam, Status},
+        MissingConfigError, ReadQueueOptions, SendCmdOptions, SpamCmdOptions,
     },
-    wallet_provider::DefaultWalletProvider,
+    fees::wait_for_fee,
+    preflight::check_connectivity,
+    queue::{self, QueueSnapshot, SnapshotEntry, diff_queue},
+    send::{SendError, SentInclusion, resolve_l2_tx, send_inclusion, send_or_resume},
+    spam::{SpamBudget, SpamRun},
+    state::{InclusionRecord, StateStore, refresh, resume, saved_in_block},
+    wallet_provider::{DefaultProvider, DefaultWalletProvider, SpamAccount},
 };
 
+use batch::{BatchReader, QueueRead, QueueState};
 use chainio::IForcedInclusionStore::{
     self, ForcedInclusionConsumed, ForcedInclusionStored, IForcedInclusionStoreErrors,
     IForcedInclusionStoreInstance,
 };
+use chainio::ITaikoInbox;
 
 /// Handle the CLI command for the Pacaya fork.
 pub async fn handle_command(cli: crate::cli::Cli) -> eyre::Result<()> {
-    let l1 = ProviderBuilder::new()
-        .wallet(cli.l1_private_key)
-        .connect_http(cli.l1_rpc_url);
-    let l2 = ProviderBuilder::new()
-        .wallet(cli.l2_private_key)
-        .connect_http(cli.l2_rpc_url);
+    let address = cli.forced_inclusion_store_address;
+    let reader = || IForcedInclusionStore::new(address, cli.l1_provider());
+    let writer = || {
+        eyre::Ok(IForcedInclusionStore::new(
+            address,
+            cli.l1_wallet_provider()?,
+        ))
+    };
+    let encoder = || BlobEncoder::new(cli.blob_encoder);
 
-    let store = IForcedInclusionStore::new(cli.forced_inclusion_store_address, l1);
-
-    match cli.command {
-        ReadQueue => read_queue(&store).await,
-        MonitorQueue => monitor_queue(&store).await,
-        Send(opts) => send_one(opts, &l2, &store).await,
-        Spam(opts) => spam(opts, &l2, &store).await,
+    match &cli.command {
+        // read-only commands, which don't need any signer
+        ReadQueue(opts) => read_queue(&reader(), opts, cli.taiko_inbox_address).await,
+        MonitorQueue => monitor_queue(&reader()).await,
+        DiffQueue(opts) => diff_queue(&opts.before, &opts.after),
+        Status => {
+            let state = StateStore::open(&cli.state_db)?;
+            status(&reader(), cli.l2_provider().as_ref(), &state).await
+        }
+        Send(opts) => {
+            // A pre-signed L2 transaction doesn't need the L2 signer.
+            let l2 = match opts.l2_raw_tx {
+                Some(_) => None,
+                None => Some(cli.l2_wallet_provider()?),
+            };
+            let l2_reader = cli.l2_provider();
+            if opts.wait_l2 && l2_reader.is_none() {
+                return Err(MissingConfigError("L2_RPC_URL").into());
+            }
+            let state = StateStore::open(&cli.state_db)?;
+            let (store, encoder) = (writer()?, encoder()?);
+            let l2 = l2.as_ref();
+            send(
+                opts.clone(),
+                l2,
+                l2_reader.as_ref(),
+                &store,
+                &encoder,
+                &state,
+            )
+            .await
+        }
+        Spam(opts) => {
+            let accounts = cli.spam_accounts(opts)?;
+            let state = StateStore::open(&cli.state_db)?;
+            spam(opts.clone(), accounts, address, &encoder()?, &state).await
+        }
+        Cancel(opts) => cancel_pending(*opts, writer()?.provider(), &encoder()?).await,
+        Preflight => preflight(&cli.l2_wallet_provider()?, &writer()?, &encoder()?).await,
     }
 }
 
-/// Send a forced inclusion transaction.
-pub async fn send_one(
+/// Send a forced inclusion, or resume the one of an interrupted run, see [`send_or_resume`].
+pub async fn send(
     opts: SendCmdOptions,
-    l2: &DefaultWalletProvider,
+    l2: Option<&DefaultWalletProvider>,
+    l2_reader: Option<&DefaultProvider>,
     store: &IForcedInclusionStoreInstance<DefaultWalletProvider>,
+    encoder: &BlobEncoder,
+    state: &StateStore,
 ) -> eyre::Result<()> {
-    // Generate the L2 transaction to be force-included. Make it a simple transfer of 1 gwei.
-    let mut l2_tx_req = TransactionRequest::default()
-        .to(Address::ZERO)
-        .value(U256::from(GWEI_TO_WEI));
+    let timeout = opts.receipt_timeout_secs.map(Duration::from_secs);
+    let resumed = resume_pending(store, l2_reader, state, timeout);
+    let send = |opts| send_one(opts, l2, store, encoder, Some(state), None);
+    send_or_resume(opts, l2, l2_reader, state, *store.address(), resumed, send).await
+}
 
-    // If a nonce delta is provided, calculate the nonce manually instead of using the
-    // default `CachedNonceManager` value.
-    if opts.nonce_delta > 0 {
-        let sender = l2.wallet().default_signer().address();
-        let pending_nonce = l2.get_transaction_count(sender).pending().await?;
-        l2_tx_req.set_nonce(pending_nonce + opts.nonce_delta);
+/// Send a forced inclusion transaction, reserving its cost from the spam `budget`, if any.
+/// Returns `None` on a dry run.
+pub async fn send_one(
+    opts: SendCmdOptions,
+    l2: Option<&DefaultWalletProvider>,
+    store: &IForcedInclusionStoreInstance<DefaultWalletProvider>,
+    encoder: &BlobEncoder,
+    state: Option<&StateStore>,
+    budget: Option<&SpamBudget>,
+) -> eyre::Result<Option<SentInclusion>> {
+    // Wait for a cheaper fee window, if requested
+    if let Some(target_gwei) = opts.wait_for_fee {
+        wait_for_fee(
+            store.provider(),
+            target_gwei,
+            Duration::from_secs(opts.wait_timeout_secs),
+            Duration::from_secs(opts.fee_poll_interval_secs),
+            || async { Ok(store.feeInGwei().call().await?) },
+        )
+        .await?;
     }
 
-    let l2_tx = l2.fill(l2_tx_req).await?.try_into_envelope()?;
-    println!(
-        "🔍 L2 tx to be force-included: nonce={}, hash={}",
-        l2_tx.nonce(),
-        l2_tx.hash()
-    );
+    let (l2_tx, l2_sender) = resolve_l2_tx(&opts, l2).await?;
+    let l2_nonce = l2_tx.nonce();
+    let l2_tx_hashes = vec![*l2_tx.hash()];
 
     // Prepare the sidecar for the forced inclusion
     let compressed_batch = rlp_encode_and_compress(&vec![l2_tx])?;
     let byte_size = compressed_batch.len() as u32;
-    let sidecar = create_blob_sidecar_from_data_async(compressed_batch).await?;
+    let sidecar_version =
+        resolve_sidecar_version(opts.blob_sidecar_version, store.provider()).await?;
+    let sidecar = encoder.encode(compressed_batch, sidecar_version).await?;
+    let blob_hashes: Vec<B256> = sidecar.versioned_hashes().collect();
 
     // Get the required fee for the forced inclusion
-    let fee_wei = U256::from(store.feeInGwei().call().await? * GWEI_TO_WEI);
-
-    // Send the forced inclusion transaction on L1
-    match store
-        .storeForcedInclusion(0, 0, byte_size)
-        .sidecar(sidecar)
-        .value(fee_wei)
-        .send()
-        .await
+    let fee_gwei = store.feeInGwei().call().await?;
+    let fee_wei = U256::from(fee_gwei * GWEI_TO_WEI);
+
+    let num_blobs = sidecar.blobs().len();
+    let call = store.storeForcedInclusion(0, 0, byte_size).value(fee_wei);
+    let call = match sidecar {
+        BlobTransactionSidecarVariant::Eip4844(sidecar) => call.sidecar(sidecar),
+        BlobTransactionSidecarVariant::Eip7594(sidecar) => call.sidecar_7594(sidecar),
+    };
+
+    let record = InclusionRecord::new(
+        *store.address(),
+        store.provider().default_signer_address(),
+        l2_sender,
+        l2_nonce,
+        l2_tx_hashes,
+        blob_hashes,
+        fee_wei,
+    );
+    let queue_index =
+        |receipt: TransactionReceipt| async move { find_queue_index(store, &receipt).await };
+    let sent = send_inclusion::<IForcedInclusionStoreErrors, _, _, _, _>(
+        call,
+        num_blobs,
+        record,
+        &opts,
+        state,
+        budget,
+        queue_index,
+    )
+    .await?;
+
+    if let Some(sent) = &sent
+        && let Err(e) = print_queue_position(store, &sent.receipt, sent.record.queue_index).await
     {
-        Ok(tx) => {
-            let receipt = tx.get_receipt().await?;
-            if receipt.status() {
-                println!(
-                    "✅ Forced inclusion batch sent successfully! Hash: {}",
-                    receipt.transaction_hash
-                );
-            } else {
-                println!(
-                    "❌ Forced inclusion batch failed! Status: {}",
-                    receipt.transaction_hash
-                );
-            }
-        }
-        Err(e) => {
-            let decoded_error = e
-                .as_decoded_interface_error::<IForcedInclusionStoreErrors>()
-                .ok_or(e)?;
+        println!("⚠️ Failed to read the queue position of the forced inclusion: {e}");
+    }
+    Ok(sent)
+}
+
+/// Compute the queue index of the forced inclusion saved by `receipt`, from the queue tail
+/// at its block.
+async fn find_queue_index<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+    receipt: &TransactionReceipt,
+) -> eyre::Result<Option<u64>> {
+    let event = ForcedInclusionStored::SIGNATURE_HASH;
+    let l1 = store.provider();
+    let Some((block, later)) = saved_in_block(l1, receipt, *store.address(), event).await? else {
+        return Ok(None);
+    };
+
+    let tail = store.tail().block(block.into()).call().await?;
+    Ok(tail.checked_sub(later + 1))
+}
+
+/// Print the queue index and position of the forced inclusion saved by `receipt`, the fee it
+/// paid, and the batch by which it must be included, from its `ForcedInclusionStored` event.
+async fn print_queue_position<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+    receipt: &TransactionReceipt,
+    queue_index: Option<u64>,
+) -> eyre::Result<()> {
+    let stored = receipt
+        .logs()
+        .iter()
+        .filter(|log| log.address() == *store.address())
+        .find_map(|log| log.log_decode::<ForcedInclusionStored>().ok())
+        .ok_or_else(|| eyre::eyre!("no ForcedInclusionStored event in the receipt"))?;
+    let fi = &stored.inner.data.forcedInclusion;
+    let fee_paid = format_ether(U256::from(fi.feeInGwei * GWEI_TO_WEI));
+
+    let inclusion_delay = store.inclusionDelay().call().await?;
+    let created = fi.createdAtBatchId;
 
-            println!("❌ Forced inclusion batch failed! Error: {decoded_error:?}",);
+    let Some(index) = queue_index else {
+        let deadline = created + u64::from(inclusion_delay);
+        println!(
+            "📥 Forced inclusion stored at an unknown queue index, created at batch {created}, due at batch {deadline} at the earliest, fee paid: {fee_paid} ETH"
+        );
+        return Ok(());
+    };
+    let head = queue_head(store).await?;
+    match index.checked_sub(head) {
+        Some(ahead) => {
+            let head_deadline = store.getOldestForcedInclusionDeadline().call().await?;
+            let deadline = due_at_batch_id(head, head_deadline, index, created, inclusion_delay);
+            println!(
+                "📥 Forced inclusion queued at index {index}, {ahead} forced inclusions ahead of it, created at batch {created}, due at batch {deadline} at the earliest, fee paid: {fee_paid} ETH"
+            )
         }
+        None => println!(
+            "📥 Forced inclusion stored at index {index}, already processed (queue head: {head}), created at batch {created}, fee paid: {fee_paid} ETH"
+        ),
+    }
+    Ok(())
+}
+
+/// Resume the forced inclusions of the L1 signer left pending by a previous run, see [`resume`].
+async fn resume_pending<P, L2>(
+    store: &IForcedInclusionStoreInstance<P>,
+    l2: Option<&L2>,
+    state: &StateStore,
+    timeout: Option<Duration>,
+) -> eyre::Result<Vec<InclusionRecord>>
+where
+    P: Provider + WalletProvider,
+    L2: Provider,
+{
+    let l1 = store.provider();
+    let l1_sender = l1.default_signer_address();
+    let head = queue_head(store).await?;
+    resume(
+        state,
+        *store.address(),
+        l1_sender,
+        l1,
+        l2,
+        head,
+        timeout,
+        |receipt| async move { find_queue_index(store, &receipt).await },
+    )
+    .await
+}
+
+/// Read the index of the oldest forced inclusion in the queue.
+pub async fn queue_head<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+) -> eyre::Result<u64> {
+    Ok(store.head().call().await?)
+}
+
+/// Refresh the forced inclusions sent to the store from the state store, and list them.
+pub async fn status<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+    l2: Option<&DefaultProvider>,
+    state: &StateStore,
+) -> eyre::Result<()> {
+    let mut records = state.list(*store.address())?;
+    if records.is_empty() {
+        println!("No forced inclusions sent to {} yet", store.address());
+        return Ok(());
     }
 
+    let head = queue_head(store).await?;
+    let l1 = store.provider();
+    refresh(state, &mut records, l1, l2, head, |receipt| async move {
+        find_queue_index(store, &receipt).await
+    })
+    .await?;
+
+    for record in &records {
+        println!("Forced inclusion {record}");
+    }
     Ok(())
 }
 
-/// Read the forced inclusion queue from the contract.
-pub async fn read_queue(
+impl From<IForcedInclusionStoreErrors> for SendError {
+    fn from(err: IForcedInclusionStoreErrors) -> Self {
+        match err {
+            IForcedInclusionStoreErrors::IncorrectFee(_) => Self::FeeTooLow,
+            IForcedInclusionStoreErrors::BlobNotFound(_) => Self::BlobNotFound,
+            IForcedInclusionStoreErrors::InvalidParams(_) => Self::InvalidParams,
+            other => Self::Store(format!("{other:?}")),
+        }
+    }
+}
+
+/// Validate the configuration, and check that both signers can afford a forced inclusion.
+pub async fn preflight(
+    l2: &DefaultWalletProvider,
     store: &IForcedInclusionStoreInstance<DefaultWalletProvider>,
+    encoder: &BlobEncoder,
 ) -> eyre::Result<()> {
-    let tail = store.tail().call().await?;
-    let head = store.head().call().await?;
-    let size = tail.saturating_sub(head);
+    check_connectivity(store.provider(), l2, *store.address()).await?;
+    println!(
+        "🔑 L1 signer: {}, L2 signer: {}",
+        store.provider().default_signer_address(),
+        l2.default_signer_address()
+    );
 
-    if size == 0 {
+    // The store of another fork doesn't expose the same fee getter.
+    let fee_gwei = store
+        .feeInGwei()
+        .call()
+        .await
+        .wrap_err("failed to read the forced inclusion fee, is FORK set correctly?")?;
+    println!("✅ Forced inclusion fee: {fee_gwei} gwei");
+
+    // Estimate a full forced inclusion, without sending it.
+    let opts = SendCmdOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+    send_one(opts, Some(l2), store, encoder, None, None).await?;
+
+    Ok(())
+}
+
+/// Read the forced inclusion queue from the contract page by page, with the batch by which
+/// each forced inclusion must be included.
+pub async fn read_queue<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+    opts: &ReadQueueOptions,
+    inbox_address: Option<Address>,
+) -> eyre::Result<()> {
+    let (block_number, block) = queue::resolve_block(store.provider(), opts.block).await?;
+    let reader = BatchReader::new(store, opts.multicall_address, block).await?;
+    // Read the queue bounds first, to select the forced inclusions to read.
+    let QueueState {
+        head,
+        tail,
+        fee_in_gwei,
+        inclusion_delay,
+        oldest_due,
+        ..
+    } = reader.read(0..0).await?.state;
+
+    // An empty queue is still saved to the snapshot.
+    let pages = queue::pages(head, tail, opts);
+    if head >= tail {
         println!("Forced inclusion queue is empty");
+    } else if pages.is_empty() {
+        println!("No forced inclusions to read in the queue [{head}, {tail})");
         return Ok(());
     }
 
-    for i in head..tail {
-        match store.getForcedInclusion(U256::from(i)).call().await {
-            Ok(fi) => println!("Forced inclusion {i}: {fi:?}\n"),
-            Err(e) => {
-                if let Some(dec) = e.as_decoded_interface_error::<IForcedInclusionStoreErrors>() {
-                    println!("Error reading forced inclusion {i}: {dec:?}");
-                } else {
-                    println!("Error reading forced inclusion {i}: {e:?}");
+    let next_batch_id = next_batch_id(store, inbox_address, block).await?;
+    println!(
+        "Next batch ID: {next_batch_id}, inclusion delay: {inclusion_delay} batches, fee: {fee_in_gwei} gwei, oldest forced inclusion due: {oldest_due}\n"
+    );
+
+    let fetch_page = |range: Range<u64>| reader.read(range);
+    let mut pages = queue::fetch_pages(pages, opts.concurrency, fetch_page);
+
+    let (mut read, mut matched) = (0, 0);
+    let mut snapshot = Vec::new();
+    while let Some(page) = pages.next().await {
+        // The deadlines are derived from the queue state read along with each page.
+        let QueueRead {
+            state,
+            forced_inclusions: mut page,
+        } = page?;
+        if opts.reverse {
+            page.reverse();
+        }
+
+        for (i, fi) in page {
+            let fi = match fi {
+                Ok(fi) => fi,
+                // A snapshot missing forced inclusions would make them look consumed.
+                Err(e) if opts.snapshot.is_some() => {
+                    return Err(e.wrap_err(format!("failed to read forced inclusion {i}")));
+                }
+                Err(e) => {
+                    println!("Error reading forced inclusion {i}: {e}");
+                    continue;
                 }
+            };
+
+            read += 1;
+            if queue::matches(
+                &opts.filter,
+                &[fi.blobHash],
+                fi.feeInGwei,
+                fi.createdAtBatchId,
+            ) {
+                matched += 1;
+                let created = fi.createdAtBatchId;
+                let deadline = due_at_batch_id(
+                    state.head,
+                    state.oldest_deadline,
+                    i,
+                    created,
+                    state.inclusion_delay,
+                );
+                let due = DueStatus::new(created, deadline, next_batch_id);
+                println!("Forced inclusion {i}: {due}\n{fi:?}\n");
+            }
+            if opts.snapshot.is_some() {
+                snapshot.push(SnapshotEntry::new(i, &fi)?);
             }
         }
     }
 
+    if queue::is_filtered(&opts.filter) {
+        println!("{matched} of the {read} forced inclusions read matched the filters");
+    }
+
+    if let Some(path) = &opts.snapshot {
+        let snapshot = QueueSnapshot {
+            store: *store.address(),
+            block_number,
+            head,
+            tail,
+            fee_in_gwei,
+            forced_inclusions: snapshot,
+        };
+        snapshot.save(path)?;
+    }
+
     Ok(())
 }
 
+/// Read the ID of the next batch to be proposed at `block` from the TaikoInbox, which is the
+/// batch ID the store compares the forced inclusion deadlines to.
+async fn next_batch_id<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+    inbox_address: Option<Address>,
+    block: BlockId,
+) -> eyre::Result<u64> {
+    let inbox_address = match inbox_address {
+        Some(address) => address,
+        None => store.inbox().block(block).call().await?,
+    };
+    let inbox = ITaikoInbox::new(inbox_address, store.provider());
+    Ok(inbox.getStats2().block(block).call().await?.numBatches)
+}
+
+/// The earliest batch ID from which the forced inclusion at `index` is due, from the deadline
+/// of the queue `head` read from the store.
+///
+/// The store only consumes a forced inclusion once it's due, and restarts the inclusion delay
+/// of the next one from the batch it consumed the previous one in, so each forced inclusion is
+/// due at least `inclusion_delay` batches after the one ahead of it.
+fn due_at_batch_id(
+    head: u64,
+    head_deadline: U256,
+    index: u64,
+    created_at_batch_id: u64,
+    inclusion_delay: u8,
+) -> u64 {
+    let delay = u64::from(inclusion_delay);
+    let after_head = head_deadline
+        .saturating_to::<u64>()
+        .saturating_add(index.saturating_sub(head).saturating_mul(delay));
+    after_head.max(created_at_batch_id + delay)
+}
+
+/// When a forced inclusion becomes due, relative to the next batch to be proposed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DueStatus {
+    created_at_batch_id: u64,
+    /// The batch ID from which the forced inclusion must be included.
+    due_at_batch_id: u64,
+    next_batch_id: u64,
+}
+
+impl DueStatus {
+    fn new(created_at_batch_id: u64, due_at_batch_id: u64, next_batch_id: u64) -> Self {
+        Self {
+            created_at_batch_id,
+            due_at_batch_id,
+            next_batch_id,
+        }
+    }
+
+    /// Whether the forced inclusion is due, i.e. must be included in the next batch.
+    fn is_due(&self) -> bool {
+        self.next_batch_id >= self.due_at_batch_id
+    }
+}
+
+impl fmt::Display for DueStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "created at batch {}, due at batch {}, ",
+            self.created_at_batch_id, self.due_at_batch_id
+        )?;
+        if !self.is_due() {
+            let remaining = self.due_at_batch_id - self.next_batch_id;
+            return write!(f, "{remaining} batches remaining");
+        }
+        match self.next_batch_id - self.due_at_batch_id {
+            0 => write!(f, "🚨 DUE in the next batch"),
+            overdue => write!(f, "🚨 OVERDUE by {overdue} batches"),
+        }
+    }
+}
+
+/// Read the number of forced inclusions in the queue.
+pub async fn queue_size<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+) -> eyre::Result<u64> {
+    let tail = store.tail().call().await?;
+    let head = store.head().call().await?;
+    Ok(tail.saturating_sub(head))
+}
+
 /// Monitor events in the forced inclusion queue
-pub async fn monitor_queue(
-    store: &IForcedInclusionStoreInstance<DefaultWalletProvider>,
+pub async fn monitor_queue<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
 ) -> eyre::Result<()> {
     let stored = store.ForcedInclusionStored_filter().filter;
     let consumed = store.ForcedInclusionConsumed_filter().filter;
@@ -172,24 +561,45 @@ pub async fn monitor_queue(
     }
 }
 
-/// Send forced inclusion transactions in a loop.
+/// Send forced inclusion transactions in a loop, concurrently from each spam account.
 pub async fn spam(
-    opts: SpamCmdOptions,
-    l2: &DefaultWalletProvider,
-    store: &IForcedInclusionStoreInstance<DefaultWalletProvider>,
+    mut opts: SpamCmdOptions,
+    accounts: Vec<SpamAccount>,
+    address: Address,
+    encoder: &BlobEncoder,
+    state: &StateStore,
 ) -> eyre::Result<()> {
-    let send_opts = SendCmdOptions::default();
+    let accounts: Vec<_> = accounts
+        .into_iter()
+        .map(|account| (IForcedInclusionStore::new(address, account.l1), account.l2))
+        .collect();
 
-    loop {
-        // NOTE: by using the default `CachedNonceManager`, the nonce will be incremented
-        // automatically by the provider without making new RPC calls.
-        if let Err(e) = send_one(send_opts, l2, store).await {
-            eprintln!("Error sending forced-inclusion: {e:?}");
-            return Err(e);
-        }
+    // Resolve the sidecar format once instead of probing the L1 on every send.
+    let (first_store, _) = accounts.first().expect("at least one spam account");
+    opts.blob_sidecar_version =
+        resolve_sidecar_version(opts.blob_sidecar_version, first_store.provider()).await?;
 
-        sleep(Duration::from_secs(opts.interval_secs)).await;
+    if accounts.len() > 1 {
+        println!(
+            "🚀 Spamming forced inclusions from {} accounts",
+            accounts.len()
+        );
     }
+
+    // The schedule and stats are shared by all accounts, which run concurrently on this task.
+    let run = SpamRun::new(&opts, state);
+    let timeout = opts.receipt_timeout_secs.map(Duration::from_secs);
+    let workers = accounts.iter().map(|(store, l2)| {
+        run.account(
+            store.provider(),
+            l2,
+            *store.address(),
+            resume_pending(store, Some(l2), state, timeout),
+            || queue_size(store),
+            |opts| send_one(opts, Some(l2), store, encoder, Some(state), run.budget()),
+        )
+    });
+    run.run(workers).await
 }
 
 /// RLP-encode and compress with zlib a given encodable object.
@@ -204,3 +614,49 @@ pub fn zlib_compress(input: &[u8]) -> std::io::Result<Bytes> {
     encoder.write_all(input)?;
     encoder.finish().map(Bytes::from)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_when_forced_inclusions_are_due() {
+        let pending = DueStatus::new(100, 112, 105);
+        assert!(!pending.is_due());
+        assert_eq!(
+            pending.to_string(),
+            "created at batch 100, due at batch 112, 7 batches remaining"
+        );
+
+        let due = DueStatus::new(100, 112, 112);
+        assert!(due.is_due());
+        assert_eq!(
+            due.to_string(),
+            "created at batch 100, due at batch 112, 🚨 DUE in the next batch"
+        );
+
+        let overdue = DueStatus::new(100, 112, 120);
+        assert!(overdue.is_due());
+        assert!(overdue.to_string().ends_with("🚨 OVERDUE by 8 batches"));
+    }
+
+    #[test]
+    fn derives_the_deadlines_of_later_forced_inclusions_from_the_head() {
+        // The store last consumed a forced inclusion at batch 98, so the head created at batch
+        // 90 is due at batch 110 rather than 102.
+        let (head, head_deadline, delay) = (5, U256::from(110), 12);
+        assert_eq!(due_at_batch_id(head, head_deadline, 5, 90, delay), 110);
+
+        // The next forced inclusions can only be due once the ones ahead of them are consumed.
+        let next_batch_id = 112;
+        let head_due = DueStatus::new(90, 110, next_batch_id);
+        assert!(head_due.is_due());
+        let second = due_at_batch_id(head, head_deadline, 6, 100, delay);
+        assert_eq!(second, 122);
+        assert!(!DueStatus::new(100, second, next_batch_id).is_due());
+        assert_eq!(due_at_batch_id(head, head_deadline, 7, 100, delay), 134);
+
+        // A forced inclusion created long after the ones ahead of it is due from its creation.
+        assert_eq!(due_at_batch_id(head, head_deadline, 7, 200, delay), 212);
+    }
+}
@@ -0,0 +1,69 @@
+use alloy::{
+    primitives::{Address, U256, utils::format_ether},
+    providers::Provider,
+};
+use eyre::WrapErr;
+
+/// An error returned when a pre-flight check fails.
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+pub enum PreflightError {
+    #[error(
+        "{layer} signer {address} can't afford the forced inclusion: balance is {} ETH, but {} ETH is required (short by {} ETH)",
+        format_ether(*.balance),
+        format_ether(*.required),
+        format_ether(.required.saturating_sub(*.balance))
+    )]
+    InsufficientFunds {
+        layer: &'static str,
+        address: Address,
+        balance: U256,
+        required: U256,
+    },
+    #[error("no contract deployed at the forced inclusion store address {0} on L1")]
+    NoStoreContract(Address),
+}
+
+/// Checks that `address` holds at least `required` wei on the given layer.
+pub async fn check_balance<P: Provider>(
+    provider: &P,
+    layer: &'static str,
+    address: Address,
+    required: U256,
+) -> eyre::Result<()> {
+    let balance = provider.get_balance(address).await?;
+    if balance < required {
+        return Err(PreflightError::InsufficientFunds {
+            layer,
+            address,
+            balance,
+            required,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Checks that both RPC endpoints are reachable, and that the forced inclusion store
+/// is deployed on L1.
+pub async fn check_connectivity<P1: Provider, P2: Provider>(
+    l1: &P1,
+    l2: &P2,
+    store: Address,
+) -> eyre::Result<()> {
+    let l1_chain_id = l1.get_chain_id().await.wrap_err("L1 RPC is unreachable")?;
+    let l1_block = l1.get_block_number().await?;
+    println!("✅ L1 RPC is reachable: chain id {l1_chain_id}, block {l1_block}");
+
+    let l2_chain_id = l2.get_chain_id().await.wrap_err("L2 RPC is unreachable")?;
+    let l2_block = l2.get_block_number().await?;
+    println!("✅ L2 RPC is reachable: chain id {l2_chain_id}, block {l2_block}");
+
+    if l1.get_code_at(store).await?.is_empty() {
+        return Err(PreflightError::NoStoreContract(store).into());
+    }
+    println!("✅ Forced inclusion store is deployed at {store}");
+
+    Ok(())
+}
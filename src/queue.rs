@@ -0,0 +1,420 @@
+use std::{collections::BTreeMap, fmt, fs, future::Future, ops::Range, path::Path};
+
+use alloy::{
+    eips::BlockId,
+    primitives::{Address, B256},
+    providers::Provider,
+};
+use eyre::WrapErr;
+use futures::{Stream, StreamExt, stream};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{QueueFilterOptions, ReadQueueOptions};
+
+/// Resolve the block to read the queue at, `latest` by default, to its number and the block ID
+/// to read at. Block tags are pinned to their current block number, so that all the reads see
+/// the same queue even if new blocks are mined meanwhile.
+pub async fn resolve_block<P: Provider>(
+    l1: &P,
+    block: Option<BlockId>,
+) -> eyre::Result<(u64, BlockId)> {
+    let block_id = block.unwrap_or_else(BlockId::latest);
+    let header = l1
+        .get_block(block_id)
+        .await?
+        .ok_or_else(|| eyre::eyre!("block {block_id} not found"))?
+        .header;
+
+    if block.is_some() {
+        println!("Reading the queue at block {}\n", header.number);
+    }
+    match block_id {
+        // Keep reading at the hash, which fails if the block was reorged out, instead of reading
+        // the canonical block at the same height.
+        BlockId::Hash(_) => Ok((header.number, block_id)),
+        BlockId::Number(_) => Ok((header.number, header.number.into())),
+    }
+}
+
+/// Split the queue entries selected by `--start` and `--limit` into pages of at most
+/// `--page-size` indices, if set, in reading order. The entries of each page are in ascending
+/// order, and must be reversed by the caller with `--reverse`.
+pub fn pages(head: u64, tail: u64, opts: &ReadQueueOptions) -> Vec<Range<u64>> {
+    let mut start = opts.start.unwrap_or(head).clamp(head, tail);
+    let mut end = tail;
+    if let Some(limit) = opts.limit {
+        if opts.reverse {
+            start = start.max(end.saturating_sub(limit));
+        } else {
+            end = end.min(start.saturating_add(limit));
+        }
+    }
+
+    let page_size = opts.page_size.unwrap_or(end - start).max(1);
+    let mut pages: Vec<_> = (start..end)
+        .step_by(page_size as usize)
+        .map(|page_start| page_start..end.min(page_start + page_size))
+        .collect();
+    if opts.reverse {
+        pages.reverse();
+    }
+    pages
+}
+
+/// Fetch the pages with `fetch_page`, at most `concurrency` at a time, yielding them in order.
+pub fn fetch_pages<'a, F, Fut>(
+    pages: Vec<Range<u64>>,
+    concurrency: usize,
+    fetch_page: F,
+) -> impl Stream<Item = Fut::Output> + 'a
+where
+    F: FnMut(Range<u64>) -> Fut + 'a,
+    Fut: Future + 'a,
+{
+    stream::iter(pages)
+        .map(fetch_page)
+        .buffered(concurrency.max(1))
+}
+
+/// Whether a forced inclusion with the given blob hashes, fee and creation point (a batch ID
+/// on Pacaya, a timestamp on Shasta) matches all the set filters.
+pub fn matches(
+    filter: &QueueFilterOptions,
+    blob_hashes: &[B256],
+    fee_in_gwei: u64,
+    created_at: u64,
+) -> bool {
+    filter
+        .blob_hash
+        .is_none_or(|hash| blob_hashes.contains(&hash))
+        && filter.min_fee_gwei.is_none_or(|min| fee_in_gwei >= min)
+        && filter.max_fee_gwei.is_none_or(|max| fee_in_gwei <= max)
+        && filter.min_created_at.is_none_or(|min| created_at >= min)
+        && filter.max_created_at.is_none_or(|max| created_at <= max)
+}
+
+/// Whether any filter is set.
+pub fn is_filtered(filter: &QueueFilterOptions) -> bool {
+    filter.blob_hash.is_some()
+        || filter.min_fee_gwei.is_some()
+        || filter.max_fee_gwei.is_some()
+        || filter.min_created_at.is_some()
+        || filter.max_created_at.is_some()
+}
+
+/// The whole forced inclusion queue at a given L1 block, saved by `read-queue --snapshot`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    /// The forced inclusion store.
+    pub store: Address,
+    /// The L1 block at which the queue was read.
+    pub block_number: u64,
+    /// The index of the oldest forced inclusion in the queue.
+    pub head: u64,
+    /// The index of the next forced inclusion to be queued.
+    pub tail: u64,
+    /// The fee of a new forced inclusion, in gwei.
+    pub fee_in_gwei: u64,
+    /// The forced inclusions in the queue, by ascending index.
+    pub forced_inclusions: Vec<SnapshotEntry>,
+}
+
+/// A forced inclusion of a [`QueueSnapshot`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// The queue index of the forced inclusion.
+    pub index: u64,
+    /// The forced inclusion, as stored by the fork.
+    pub forced_inclusion: serde_json::Value,
+}
+
+impl SnapshotEntry {
+    /// Builds the snapshot entry of the forced inclusion at `index`.
+    pub fn new(index: u64, forced_inclusion: &impl Serialize) -> eyre::Result<Self> {
+        Ok(Self {
+            index,
+            forced_inclusion: serde_json::to_value(forced_inclusion)?,
+        })
+    }
+}
+
+impl QueueSnapshot {
+    /// Saves the snapshot to `path` as JSON, sorting the forced inclusions by index.
+    pub fn save(mut self, path: &Path) -> eyre::Result<()> {
+        self.forced_inclusions.sort_by_key(|entry| entry.index);
+        let json = serde_json::to_string_pretty(&self)?;
+        fs::write(path, json)
+            .wrap_err_with(|| format!("failed to write the queue snapshot {}", path.display()))?;
+        println!(
+            "📸 Saved the {} forced inclusions of the queue at block {} to {}",
+            self.forced_inclusions.len(),
+            self.block_number,
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Loads a snapshot saved by [`QueueSnapshot::save`].
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let json = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read the queue snapshot {}", path.display()))?;
+        serde_json::from_str(&json)
+            .wrap_err_with(|| format!("invalid queue snapshot {}", path.display()))
+    }
+}
+
+/// The changes of the forced inclusion queue between two snapshots.
+#[derive(Debug)]
+pub struct QueueDiff<'a> {
+    before: &'a QueueSnapshot,
+    after: &'a QueueSnapshot,
+    /// The forced inclusions of the first snapshot which left the queue.
+    consumed: Vec<&'a SnapshotEntry>,
+    /// The forced inclusions of the second snapshot which joined the queue.
+    added: Vec<&'a SnapshotEntry>,
+    /// The forced inclusions at the same index in both snapshots, but which differ.
+    changed: Vec<(&'a SnapshotEntry, &'a SnapshotEntry)>,
+}
+
+impl<'a> QueueDiff<'a> {
+    /// Compares the `before` and `after` snapshots of the same queue.
+    pub fn new(before: &'a QueueSnapshot, after: &'a QueueSnapshot) -> Self {
+        let after_entries: BTreeMap<_, _> = after
+            .forced_inclusions
+            .iter()
+            .map(|entry| (entry.index, entry))
+            .collect();
+
+        let mut consumed = Vec::new();
+        let mut changed = Vec::new();
+        for entry in &before.forced_inclusions {
+            match after_entries.get(&entry.index) {
+                Some(other) if other.forced_inclusion != entry.forced_inclusion => {
+                    changed.push((entry, *other))
+                }
+                Some(_) => {}
+                None if entry.index < after.head => consumed.push(entry),
+                None => {}
+            }
+        }
+
+        let added = after
+            .forced_inclusions
+            .iter()
+            .filter(|entry| entry.index >= before.tail)
+            .collect();
+
+        Self {
+            before,
+            after,
+            consumed,
+            added,
+            changed,
+        }
+    }
+
+    /// The number of forced inclusions queued and consumed between the snapshots, which
+    /// neither of them holds.
+    fn unseen(&self) -> u64 {
+        self.after.head.saturating_sub(self.before.tail)
+    }
+}
+
+impl fmt::Display for QueueDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (before, after) = (self.before, self.after);
+        if before.store != after.store {
+            writeln!(
+                f,
+                "⚠️ The snapshots are of different stores: {} and {}",
+                before.store, after.store
+            )?;
+        }
+        writeln!(
+            f,
+            "Queue changes from block {} to block {}:",
+            before.block_number, after.block_number
+        )?;
+        writeln!(
+            f,
+            "  head: {} -> {} ({})",
+            before.head,
+            after.head,
+            delta(before.head, after.head)
+        )?;
+        writeln!(
+            f,
+            "  tail: {} -> {} ({})",
+            before.tail,
+            after.tail,
+            delta(before.tail, after.tail)
+        )?;
+        writeln!(
+            f,
+            "  fee: {} -> {} gwei ({})",
+            before.fee_in_gwei,
+            after.fee_in_gwei,
+            delta(before.fee_in_gwei, after.fee_in_gwei)
+        )?;
+
+        for entry in &self.consumed {
+            writeln!(
+                f,
+                "➖ Consumed forced inclusion {}: {}",
+                entry.index, entry.forced_inclusion
+            )?;
+        }
+        if self.unseen() > 0 {
+            writeln!(
+                f,
+                "➖ {} forced inclusions were queued and consumed between the snapshots",
+                self.unseen()
+            )?;
+        }
+        for entry in &self.added {
+            writeln!(
+                f,
+                "➕ Added forced inclusion {}: {}",
+                entry.index, entry.forced_inclusion
+            )?;
+        }
+        for (before, after) in &self.changed {
+            writeln!(
+                f,
+                "✏️ Changed forced inclusion {}: {} -> {}",
+                before.index, before.forced_inclusion, after.forced_inclusion
+            )?;
+        }
+
+        write!(
+            f,
+            "{} consumed, {} added, {} changed",
+            self.consumed.len() as u64 + self.unseen(),
+            self.added.len(),
+            self.changed.len()
+        )
+    }
+}
+
+/// Formats the signed difference from `before` to `after`.
+fn delta(before: u64, after: u64) -> String {
+    if after >= before {
+        format!("+{}", after - before)
+    } else {
+        format!("-{}", before - after)
+    }
+}
+
+/// Compares two queue snapshots saved by `read-queue --snapshot`.
+pub fn diff_queue(before: &Path, after: &Path) -> eyre::Result<()> {
+    let before = QueueSnapshot::load(before)?;
+    let after = QueueSnapshot::load(after)?;
+    println!("{}", QueueDiff::new(&before, &after));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_the_selected_entries() {
+        let opts = ReadQueueOptions {
+            page_size: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(pages(10, 20, &opts), [10..14, 14..18, 18..20]);
+        assert!(pages(10, 10, &opts).is_empty());
+
+        // Without a page size, the selected entries are read in a single page.
+        let opts = ReadQueueOptions::default();
+        let single = 10..20;
+        assert_eq!(pages(10, 20, &opts), [single]);
+        assert!(pages(10, 10, &opts).is_empty());
+
+        let opts = ReadQueueOptions {
+            start: Some(12),
+            limit: Some(5),
+            page_size: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(pages(10, 20, &opts), [12..16, 16..17]);
+
+        // The newest entries are read first, and the start is clamped to the head.
+        let opts = ReadQueueOptions {
+            start: Some(0),
+            limit: Some(6),
+            page_size: Some(4),
+            reverse: true,
+            ..Default::default()
+        };
+        assert_eq!(pages(10, 20, &opts), [18..20, 14..18]);
+    }
+
+    #[test]
+    fn filters_forced_inclusions() {
+        let hash = B256::repeat_byte(1);
+        assert!(matches(&QueueFilterOptions::default(), &[], 10, 100));
+
+        let filter = QueueFilterOptions {
+            blob_hash: Some(hash),
+            min_fee_gwei: Some(10),
+            max_created_at: Some(100),
+            ..Default::default()
+        };
+        assert!(is_filtered(&filter));
+        assert!(matches(&filter, &[B256::ZERO, hash], 10, 100));
+        assert!(!matches(&filter, &[B256::ZERO], 10, 100));
+        assert!(!matches(&filter, &[hash], 9, 100));
+        assert!(!matches(&filter, &[hash], 10, 101));
+    }
+
+    fn snapshot(block_number: u64, head: u64, tail: u64, fee_in_gwei: u64) -> QueueSnapshot {
+        let forced_inclusions = (head..tail)
+            .map(|index| SnapshotEntry::new(index, &serde_json::json!({ "id": index })).unwrap())
+            .collect();
+        QueueSnapshot {
+            store: Address::ZERO,
+            block_number,
+            head,
+            tail,
+            fee_in_gwei,
+            forced_inclusions,
+        }
+    }
+
+    #[test]
+    fn diffs_queue_snapshots() {
+        let before = snapshot(100, 10, 14, 120);
+        let after = snapshot(110, 12, 17, 100);
+
+        let diff = QueueDiff::new(&before, &after);
+        let consumed: Vec<_> = diff.consumed.iter().map(|entry| entry.index).collect();
+        let added: Vec<_> = diff.added.iter().map(|entry| entry.index).collect();
+        assert_eq!(consumed, [10, 11]);
+        assert_eq!(added, [14, 15, 16]);
+        assert!(diff.changed.is_empty());
+        assert_eq!(diff.unseen(), 0);
+
+        let output = diff.to_string();
+        assert!(output.contains("head: 10 -> 12 (+2)"));
+        assert!(output.contains("fee: 120 -> 100 gwei (-20)"));
+        assert!(output.ends_with("2 consumed, 3 added, 0 changed"));
+
+        // Forced inclusions queued after the first snapshot may be gone by the second one.
+        let later = snapshot(200, 20, 21, 100);
+        let diff = QueueDiff::new(&before, &later);
+        assert_eq!(diff.consumed.len(), 4);
+        assert_eq!(diff.unseen(), 6);
+    }
+
+    #[test]
+    fn saves_and_loads_queue_snapshots() {
+        let path =
+            std::env::temp_dir().join(format!("fi-toolbox-queue-{}.json", std::process::id()));
+        let snapshot = snapshot(100, 10, 14, 120);
+        snapshot.clone().save(&path).unwrap();
+        assert_eq!(QueueSnapshot::load(&path).unwrap(), snapshot);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+use std::time::Duration;
+
+use alloy::{
+    consensus::SignableTransaction,
+    hex,
+    network::TxSigner,
+    primitives::{Address, B256, B512, Signature, SignatureError, keccak256},
+    signers,
+    transports::http::reqwest::{self, Url},
+};
+
+/// Timeout of a single signing request to the remote signer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An error returned by the remote signer.
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+pub enum RemoteSignerError {
+    #[error("remote signer request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("remote signer refused to sign with {address}: HTTP {status}: {body}")]
+    Refused {
+        address: Address,
+        status: reqwest::StatusCode,
+        body: String,
+    },
+    #[error("remote signer returned an invalid signature: {0}")]
+    InvalidSignature(#[from] SignatureError),
+    #[error("remote signer returned a signature by {actual}, expected {expected}")]
+    WrongSigner { expected: Address, actual: Address },
+}
+
+/// A transaction signer that delegates signing to a Web3Signer-compatible HTTP endpoint.
+///
+/// Transactions are signed with `POST {url}/api/v1/eth1/sign/{identifier}`, which signs the
+/// keccak256 hash of the given data with the key identified by its uncompressed public key,
+/// so that the private key never leaves the remote signer.
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: Url,
+    public_key: B512,
+    address: Address,
+}
+
+impl RemoteSigner {
+    /// Creates a signer for the key held by the remote signer at `url`, identified by its
+    /// uncompressed secp256k1 `public_key` without the `0x04` prefix.
+    pub fn new(url: Url, public_key: B512) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("TLS backend is available");
+
+        Self {
+            client,
+            url,
+            public_key,
+            address: Address::from_raw_public_key(public_key.as_slice()),
+        }
+    }
+
+    /// Signs the keccak256 hash of `data`, and checks that it was signed by our address.
+    pub async fn sign_data(&self, data: &[u8]) -> Result<Signature, RemoteSignerError> {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .expect("HTTP URLs can be a base")
+            .pop_if_empty()
+            .extend(["api", "v1", "eth1", "sign", &self.public_key.to_string()]);
+
+        let response = self
+            .client
+            .post(url)
+            .json(&serde_json::json!({ "data": hex::encode_prefixed(data) }))
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(RemoteSignerError::Refused {
+                address: self.address,
+                status,
+                body,
+            });
+        }
+
+        // The signature is returned as plain text, but accept a JSON string too.
+        let raw = hex::decode(body.trim().trim_matches('"')).map_err(SignatureError::from)?;
+        let signature = Signature::from_raw(&raw)?;
+
+        let hash: B256 = keccak256(data);
+        let actual = signature.recover_address_from_prehash(&hash)?;
+        if actual != self.address {
+            return Err(RemoteSignerError::WrongSigner {
+                expected: self.address,
+                actual,
+            });
+        }
+
+        Ok(signature)
+    }
+}
+
+#[async_trait::async_trait]
+impl TxSigner<Signature> for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(
+        &self,
+        tx: &mut dyn SignableTransaction<Signature>,
+    ) -> signers::Result<Signature> {
+        self.sign_data(&tx.encoded_for_signing())
+            .await
+            .map_err(signers::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        consensus::{TxEip1559, TypedTransaction},
+        network::{Ethereum, EthereumWallet, NetworkWallet},
+        primitives::{TxKind, U256},
+        signers::{SignerSync, local::PrivateKeySigner},
+    };
+    use axum::{
+        Json, Router,
+        extract::{Path, State},
+        http::StatusCode,
+        routing::post,
+    };
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// The Web3Signer identifier of `key`: its uncompressed public key without the `0x04` prefix.
+    fn public_key(key: &PrivateKeySigner) -> B512 {
+        let point = key.credential().verifying_key().to_encoded_point(false);
+        B512::from_slice(&point.as_bytes()[1..])
+    }
+
+    /// Serves a stand-in Web3Signer holding the given key, and returns its URL.
+    ///
+    /// Like Web3Signer, keys are identified by their lowercase hex public key, and the
+    /// signature is returned as plain text.
+    async fn serve_signer(key: PrivateKeySigner) -> Url {
+        async fn sign(
+            State(key): State<PrivateKeySigner>,
+            Path(identifier): Path<String>,
+            Json(req): Json<serde_json::Value>,
+        ) -> (StatusCode, String) {
+            if identifier != public_key(&key).to_string() {
+                return (StatusCode::NOT_FOUND, "Public Key not found".to_string());
+            }
+            let data = hex::decode(req["data"].as_str().unwrap()).unwrap();
+            let signature = key.sign_hash_sync(&keccak256(data)).unwrap();
+            (StatusCode::OK, hex::encode_prefixed(signature.as_bytes()))
+        }
+
+        let app = Router::new()
+            .route("/api/v1/eth1/sign/{identifier}", post(sign))
+            .with_state(key);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        format!("http://{addr}").parse().unwrap()
+    }
+
+    fn transfer() -> TxEip1559 {
+        TxEip1559 {
+            chain_id: 167_000,
+            nonce: 7,
+            gas_limit: 21_000,
+            max_fee_per_gas: 2_000_000_000,
+            max_priority_fee_per_gas: 1_000_000_000,
+            to: TxKind::Call(Address::ZERO),
+            value: U256::from(1),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn signs_transactions_with_the_remote_key() {
+        let key = PrivateKeySigner::random();
+        let url = serve_signer(key.clone()).await;
+        let signer = RemoteSigner::new(url, public_key(&key));
+        assert_eq!(signer.address(), key.address());
+
+        let mut tx = transfer();
+        let signature = signer.sign_transaction(&mut tx).await.unwrap();
+        let signer_address = signature
+            .recover_address_from_prehash(&tx.signature_hash())
+            .unwrap();
+        assert_eq!(signer_address, key.address());
+    }
+
+    #[tokio::test]
+    async fn plugs_into_the_ethereum_wallet() {
+        let key = PrivateKeySigner::random();
+        let url = serve_signer(key.clone()).await;
+        let wallet = EthereumWallet::from(RemoteSigner::new(url, public_key(&key)));
+
+        let tx = TypedTransaction::Eip1559(transfer());
+        let envelope = NetworkWallet::<Ethereum>::sign_transaction_from(&wallet, key.address(), tx)
+            .await
+            .unwrap();
+        let signer_address = envelope
+            .signature()
+            .recover_address_from_prehash(&envelope.signature_hash())
+            .unwrap();
+        assert_eq!(signer_address, key.address());
+    }
+
+    #[tokio::test]
+    async fn surfaces_unknown_keys() {
+        let url = serve_signer(PrivateKeySigner::random()).await;
+        let unknown = public_key(&PrivateKeySigner::random());
+        let signer = RemoteSigner::new(url, unknown);
+
+        let err = signer.sign_data(b"forced inclusion").await.unwrap_err();
+        assert!(matches!(
+            err,
+            RemoteSignerError::Refused {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use alloy::{
+    contract::Error as ContractError,
+    transports::{
+        RpcError, TransportError,
+        layers::{RateLimitRetryPolicy, RetryPolicy},
+    },
+};
+
+use crate::{cli::RetryOptions, send::SendError};
+
+/// The maximum delay between two retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Lowercase fragments of the RPC error messages of transient failures, which usually
+/// succeed when the forced inclusion is rebuilt and sent again.
+const TRANSIENT_MESSAGES: &[&str] = &[
+    "nonce too low",
+    "replacement transaction underpriced",
+    "already known",
+    "timeout",
+    "timed out",
+];
+
+/// Whether a failed forced inclusion can be retried: connection errors, timeouts, rate
+/// limits and nonce races, as opposed to reverts and configuration errors.
+///
+/// RPC errors are only transient before the forced inclusion is broadcast, as
+/// [`send_with_fee_bumps`](crate::send::send_with_fee_bumps) doesn't return them afterwards.
+pub fn is_transient(err: &eyre::Report) -> bool {
+    if let Some(err) = err.downcast_ref::<SendError>() {
+        return match err {
+            SendError::Rpc(err) => is_transient_rpc(err),
+            SendError::Contract(err) => is_transient_contract(err),
+            // The transaction may still be mined, so it's not safe to send it again.
+            _ => false,
+        };
+    }
+
+    if let Some(err) = err.downcast_ref::<ContractError>() {
+        return is_transient_contract(err);
+    }
+
+    err.downcast_ref::<TransportError>()
+        .is_some_and(is_transient_rpc)
+}
+
+fn is_transient_contract(err: &ContractError) -> bool {
+    match err {
+        ContractError::TransportError(err) => is_transient_rpc(err),
+        _ => false,
+    }
+}
+
+fn is_transient_rpc(err: &TransportError) -> bool {
+    match err {
+        RpcError::Transport(_) => true,
+        RpcError::ErrorResp(payload) => {
+            let message = payload.message.to_lowercase();
+            TRANSIENT_MESSAGES.iter().any(|m| message.contains(m))
+                || RateLimitRetryPolicy::default().should_retry(err)
+        }
+        _ => false,
+    }
+}
+
+/// The delay before the given retry (starting at 0), doubling on each retry up to a minute.
+pub fn backoff(opts: &RetryOptions, retry: u32) -> Duration {
+    Duration::from_secs(opts.retry_backoff_secs)
+        .saturating_mul(2u32.saturating_pow(retry))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        primitives::B256,
+        transports::{TransportErrorKind, http::reqwest::StatusCode},
+    };
+
+    use super::*;
+
+    fn error_resp(message: &str) -> TransportError {
+        let payload = serde_json::json!({ "code": -32000, "message": message });
+        RpcError::ErrorResp(serde_json::from_value(payload).unwrap())
+    }
+
+    #[test]
+    fn classifies_transient_errors() {
+        let transient = [
+            eyre::Report::new(error_resp("nonce too low: next nonce 8, tx nonce 7")),
+            eyre::Report::new(SendError::Contract(ContractError::TransportError(
+                error_resp("replacement transaction underpriced"),
+            ))),
+            eyre::Report::new(SendError::Rpc(TransportErrorKind::http_error(
+                StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+                String::new(),
+            ))),
+        ];
+        for err in transient {
+            assert!(is_transient(&err), "{err}");
+        }
+
+        let permanent = [
+            eyre::Report::new(error_resp("insufficient funds for gas * price + value")),
+            eyre::Report::new(SendError::ReceiptTimeout {
+                tx_hash: B256::ZERO,
+                timeout: Duration::from_secs(60),
+            }),
+            eyre::Report::new(SendError::FeeTooLow),
+        ];
+        for err in permanent {
+            assert!(!is_transient(&err), "{err}");
+        }
+    }
+
+    #[test]
+    fn doubles_the_backoff_up_to_a_minute() {
+        let opts = RetryOptions::default();
+        let delays: Vec<_> = (0..7)
+            .map(|retry| backoff(&opts, retry).as_secs())
+            .collect();
+        assert_eq!(delays, [2, 4, 8, 16, 32, 60, 60]);
+    }
+}
@@ -0,0 +1,229 @@
+use std::time::Duration;
+
+use alloy::providers::Provider;
+use futures::StreamExt;
+use tokio::time::{Instant, sleep_until, timeout_at};
+
+use crate::cli::{LoadProfile, LoadProfileOptions};
+
+/// Hands out the send slots of the spam accounts following the load profile, until the
+/// count or duration limit is reached.
+#[derive(Debug)]
+pub struct Schedule {
+    opts: LoadProfileOptions,
+    started: Instant,
+    /// When the next slot opens, for the time-based profiles.
+    next_at: Instant,
+    /// The number of slots handed out so far.
+    slots: u64,
+    /// The L1 block the current burst started at, and the number of slots handed out in it.
+    burst: Option<(u64, u64)>,
+    /// The L1 block of the last check of the block-aligned profiles.
+    last_block: Option<u64>,
+}
+
+impl Schedule {
+    /// Creates a schedule starting now, with its first slot open right away.
+    pub fn new(opts: LoadProfileOptions) -> Self {
+        let now = Instant::now();
+        Self {
+            opts,
+            started: now,
+            next_at: now,
+            slots: 0,
+            burst: None,
+            last_block: None,
+        }
+    }
+
+    /// Waits for the next send slot. Returns `false` once the count or duration limit is
+    /// reached, after which no more forced inclusions should be sent.
+    ///
+    /// The queue size is read with `queue_size`, as it's exposed differently by each fork.
+    pub async fn next<P, F, Fut>(&mut self, l1: &P, mut queue_size: F) -> eyre::Result<bool>
+    where
+        P: Provider,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = eyre::Result<u64>>,
+    {
+        if self.opts.count.is_some_and(|count| self.slots >= count) || self.expired() {
+            return Ok(false);
+        }
+
+        match self.opts.profile {
+            LoadProfile::Constant | LoadProfile::Ramp | LoadProfile::Poisson => {
+                if !self.sleep_until(self.next_at).await {
+                    return Ok(false);
+                }
+                // Schedule from now if the slot was taken late, instead of catching up in a burst.
+                self.next_at = self.next_at.max(Instant::now()) + self.interval();
+            }
+            LoadProfile::Bursty => {
+                let (start, sent) = match self.burst {
+                    Some((start, sent)) if sent < self.opts.burst_size.max(1) => (start, sent),
+                    Some((start, _)) => {
+                        let next_burst = start + self.opts.burst_every_blocks;
+                        match self.wait_for_block(l1, next_burst).await? {
+                            Some(block) => (block, 0),
+                            None => return Ok(false),
+                        }
+                    }
+                    None => (l1.get_block_number().await?, 0),
+                };
+                self.burst = Some((start, sent + 1));
+            }
+            LoadProfile::Blocks => {
+                if !self.wait_for_next_block(l1).await? {
+                    return Ok(false);
+                }
+            }
+            LoadProfile::FillQueue => loop {
+                if !self.wait_for_next_block(l1).await? {
+                    return Ok(false);
+                }
+                if queue_size().await? < self.opts.target_queue_size {
+                    break;
+                }
+            },
+        }
+
+        self.slots += 1;
+        Ok(true)
+    }
+
+    /// The number of slots handed out so far.
+    pub fn slots(&self) -> u64 {
+        self.slots
+    }
+
+    /// The interval until the next slot of the time-based profiles.
+    fn interval(&self) -> Duration {
+        let mean = self.opts.interval_secs as f64;
+        let secs = match self.opts.profile {
+            LoadProfile::Ramp => {
+                let elapsed = self.started.elapsed().as_secs_f64();
+                let progress = (elapsed / self.opts.ramp_duration_secs as f64).min(1.0);
+                // Interpolate the rate rather than the interval, for a linear increase in load.
+                let from = 1.0 / mean;
+                let to = 1.0 / self.opts.ramp_to_interval_secs as f64;
+                1.0 / (from + (to - from) * progress)
+            }
+            LoadProfile::Poisson => -mean * (1.0 - rand::random::<f64>()).ln(),
+            _ => mean,
+        };
+
+        Duration::try_from_secs_f64(secs).unwrap_or(Duration::ZERO)
+    }
+
+    /// Waits for the L1 head to move `every_blocks` past the last check, which happens right
+    /// away the first time. Returns `false` if the duration limit is reached before.
+    async fn wait_for_next_block<P: Provider>(&mut self, l1: &P) -> eyre::Result<bool> {
+        let block = match self.last_block {
+            Some(last) => {
+                let next = last + self.opts.every_blocks.max(1);
+                match self.wait_for_block(l1, next).await? {
+                    Some(block) => block,
+                    None => return Ok(false),
+                }
+            }
+            None => l1.get_block_number().await?,
+        };
+
+        self.last_block = Some(block);
+        Ok(true)
+    }
+
+    /// Waits for the L1 to reach `block`, and returns the current block, or `None` if the
+    /// duration limit is reached before.
+    ///
+    /// The L1 head is checked again on each new block of the `watch_blocks` stream.
+    async fn wait_for_block<P: Provider>(&self, l1: &P, block: u64) -> eyre::Result<Option<u64>> {
+        let current = l1.get_block_number().await?;
+        if current >= block {
+            return Ok(Some(current));
+        }
+
+        let mut new_blocks = l1.watch_blocks().await?.into_stream();
+        loop {
+            let next = match self.deadline() {
+                Some(deadline) => match timeout_at(deadline, new_blocks.next()).await {
+                    Ok(next) => next,
+                    Err(_) => return Ok(None),
+                },
+                None => new_blocks.next().await,
+            };
+            if next.is_none() {
+                eyre::bail!("the L1 block stream ended while waiting for block {block}");
+            }
+
+            let current = l1.get_block_number().await?;
+            if current >= block {
+                return Ok(Some(current));
+            }
+        }
+    }
+
+    /// Sleeps until `at`, or returns `false` right away if the duration limit is reached before.
+    async fn sleep_until(&self, at: Instant) -> bool {
+        if self.deadline().is_some_and(|deadline| at > deadline) {
+            return false;
+        }
+
+        sleep_until(at).await;
+        true
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        let duration = Duration::from_secs(self.opts.duration_secs?);
+        Some(self.started + duration)
+    }
+
+    fn expired(&self) -> bool {
+        self.deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::providers::ProviderBuilder;
+
+    use super::*;
+
+    #[test]
+    fn ramps_the_rate_linearly() {
+        let mut schedule = Schedule::new(LoadProfileOptions {
+            profile: LoadProfile::Ramp,
+            interval_secs: 10,
+            ramp_to_interval_secs: 2,
+            ramp_duration_secs: 10,
+            ..Default::default()
+        });
+        assert!((schedule.interval().as_secs_f64() - 10.0).abs() < 0.01);
+
+        // Halfway through, the rate is halfway between 1/10 and 1/2 per second.
+        schedule.started -= Duration::from_secs(5);
+        assert!((schedule.interval().as_secs_f64() - 1.0 / 0.3).abs() < 0.01);
+
+        // After the ramp, the final rate is kept.
+        schedule.opts.ramp_duration_secs = 1;
+        assert!((schedule.interval().as_secs_f64() - 2.0).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_count_limit() {
+        let l1 = ProviderBuilder::new().connect_http("http://127.0.0.1:1".parse().unwrap());
+        let mut schedule = Schedule::new(LoadProfileOptions {
+            interval_secs: 0,
+            count: Some(3),
+            ..Default::default()
+        });
+
+        let queue_size = || async { Ok(0) };
+        for _ in 0..3 {
+            assert!(schedule.next(&l1, queue_size).await.unwrap());
+        }
+        assert!(!schedule.next(&l1, queue_size).await.unwrap());
+        assert_eq!(schedule.slots(), 3);
+    }
+}
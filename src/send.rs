@@ -0,0 +1,691 @@
+use std::{
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+
+use alloy::{
+    consensus::{Transaction, TxEnvelope, constants::GWEI_TO_WEI},
+    contract::{CallBuilder, CallDecoder, Error as ContractError},
+    eips::eip2718::Decodable2718,
+    network::TransactionBuilder,
+    primitives::{Address, B256, U256, utils::format_ether},
+    providers::{Provider, WalletProvider},
+    rpc::types::{TransactionReceipt, TransactionRequest},
+    sol_types::SolInterface,
+    transports::TransportError,
+};
+use eyre::WrapErr;
+use tokio::time::sleep;
+
+use crate::{
+    cli::{FeeBumpOptions, FeeCapOptions, MissingConfigError, SendCmdOptions},
+    fees::InclusionCost,
+    preflight::check_balance,
+    spam::SpamBudget,
+    state::{InclusionRecord, InclusionStatus, StateStore, resume_l2_nonce},
+    wallet_provider::DefaultWalletProvider,
+};
+
+/// The interval between receipt checks while waiting for the L1 transaction to be mined.
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// The interval between receipt checks while waiting for the L2 inclusion.
+const L2_POLL_INTERVAL: Duration = Duration::from_secs(6);
+
+/// The outcome of sending a forced inclusion transaction on L1.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SendOutcome {
+    /// Whether the forced inclusion was saved in the store.
+    pub saved: bool,
+    /// The amount of wei spent by the L1 transaction, including the inclusion fee.
+    pub cost: U256,
+}
+
+impl SendOutcome {
+    /// Builds the outcome of a mined forced inclusion transaction that paid `inclusion_fee`.
+    pub fn from_receipt(receipt: &TransactionReceipt, inclusion_fee: U256) -> Self {
+        let gas_cost = U256::from(receipt.gas_used) * U256::from(receipt.effective_gas_price);
+        let blob_gas_cost = U256::from(receipt.blob_gas_used.unwrap_or_default())
+            * U256::from(receipt.blob_gas_price.unwrap_or_default());
+
+        // A reverted transaction doesn't transfer the inclusion fee.
+        let saved = receipt.status();
+        let fee = if saved { inclusion_fee } else { U256::ZERO };
+
+        Self {
+            saved,
+            cost: fee + gas_cost + blob_gas_cost,
+        }
+    }
+}
+
+/// A forced inclusion transaction mined on L1.
+#[derive(Debug)]
+pub struct SentInclusion {
+    /// The receipt of the L1 transaction.
+    pub receipt: TransactionReceipt,
+    /// The outcome of the L1 transaction.
+    pub outcome: SendOutcome,
+    /// The forced inclusion, as saved in the state store.
+    pub record: InclusionRecord,
+}
+
+/// A structured reason for a failed forced inclusion send.
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+pub enum SendError {
+    #[error("forced inclusion fee is too low")]
+    FeeTooLow,
+    #[error("blob not found in the forced inclusion transaction")]
+    BlobNotFound,
+    #[error("invalid forced inclusion parameters")]
+    InvalidParams,
+    #[error("forced inclusion store error: {0}")]
+    Store(String),
+    #[error("forced inclusion transaction {tx_hash} reverted: {reason}")]
+    Reverted {
+        tx_hash: B256,
+        reason: String,
+        /// The amount of wei spent by the reverted transaction.
+        cost: U256,
+    },
+    #[error("timed out after {}s waiting for the receipt of {tx_hash}", .timeout.as_secs())]
+    ReceiptTimeout { tx_hash: B256, timeout: Duration },
+    #[error("timed out after {}s waiting for the L2 inclusion of {tx_hash}", .timeout.as_secs())]
+    L2InclusionTimeout { tx_hash: B256, timeout: Duration },
+    #[error(
+        "forced inclusion with nonce {nonce} of {l1_sender} from a previous run still pending after {}s, replace it with the cancel command",
+        .timeout.as_secs()
+    )]
+    StuckPending {
+        l1_sender: Address,
+        nonce: u64,
+        timeout: Duration,
+    },
+    #[error(transparent)]
+    Contract(#[from] ContractError),
+    #[error(transparent)]
+    Rpc(#[from] TransportError),
+}
+
+impl SendError {
+    /// Decodes a contract error into the matching store error `E`, if any.
+    pub fn decode<E>(self) -> Self
+    where
+        E: SolInterface + Into<Self>,
+    {
+        match self {
+            Self::Contract(e) => match e.as_decoded_interface_error::<E>() {
+                Some(decoded) => decoded.into(),
+                None => Self::Contract(e),
+            },
+            other => other,
+        }
+    }
+
+    /// Builds the error for a reverted receipt, replaying the transaction with `eth_call`
+    /// on top of its parent block to recover the revert reason, decoded as a store error `E`,
+    /// see [`revert_reason`].
+    pub async fn reverted<E, P>(provider: &P, receipt: &TransactionReceipt, cost: U256) -> Self
+    where
+        E: SolInterface + Debug,
+        P: Provider,
+    {
+        Self::Reverted {
+            tx_hash: receipt.transaction_hash,
+            reason: revert_reason::<E, P>(provider, receipt).await,
+            cost,
+        }
+    }
+}
+
+/// Replays a reverted transaction at the parent of its block, and returns its revert reason.
+///
+/// The replay runs on the state at the end of the parent block, without the transactions mined
+/// before this one in the same block. A revert caused by one of them, e.g. another forced
+/// inclusion raising the fee, may thus not be reproduced, and is then reported as unknown.
+async fn revert_reason<E, P>(provider: &P, receipt: &TransactionReceipt) -> String
+where
+    E: SolInterface + Debug,
+    P: Provider,
+{
+    let Some(block) = receipt.block_number else {
+        return "unknown (receipt has no block number)".to_string();
+    };
+
+    let tx = match provider
+        .get_transaction_by_hash(receipt.transaction_hash)
+        .await
+    {
+        Ok(Some(tx)) => tx,
+        Ok(None) => return "unknown (transaction not found)".to_string(),
+        Err(e) => return format!("unknown (failed to fetch transaction: {e})"),
+    };
+
+    match provider
+        .call(tx.into_request())
+        .block(block.saturating_sub(1).into())
+        .await
+    {
+        Ok(_) => "unknown (transaction succeeds when replayed)".to_string(),
+        Err(e) => match e.as_error_resp() {
+            Some(payload) => payload
+                .as_decoded_interface_error::<E>()
+                .map(|decoded| format!("{decoded:?}"))
+                .or_else(|| {
+                    payload
+                        .as_revert_data()
+                        .map(|data| format!("revert data {data}"))
+                })
+                .unwrap_or_else(|| payload.message.to_string()),
+            None => format!("unknown (replay failed: {e})"),
+        },
+    }
+}
+
+/// Sends the forced inclusion call with the given fees, and waits for it to be mined.
+///
+/// If the transaction isn't mined after `bump.resubmit_after_blocks` L1 blocks, it is
+/// replaced by the same call with the same nonce and bumped fees, reusing the sidecar.
+/// Bumping stops after `bump.max_fee_bumps` replacements, or once the bumped fees would
+/// exceed the fee caps or the spam budget, after which we keep waiting for any of the
+/// broadcast transactions, until `receipt_timeout` expires, if any.
+///
+/// Only the errors raised before the first broadcast are returned as [`SendError::Rpc`] or
+/// [`SendError::Contract`]: once a transaction is broadcast, RPC errors are retried until it's
+/// mined or `receipt_timeout` expires, so that it is never sent twice.
+///
+/// If a spam `budget` is given, `cost` must have been reserved from it: bumps reserve the extra
+/// cost, and the reservation is settled to the actual cost once mined. It's released if
+/// nothing was broadcast, and kept if the transaction may still be mined.
+///
+/// `on_broadcast` is called with the pinned nonce and the hashes of all the broadcast
+/// transactions after each submission, so that they can be tracked across restarts.
+pub async fn send_with_fee_bumps<P, D, B>(
+    call: CallBuilder<&P, D>,
+    mut cost: InclusionCost,
+    bump: &FeeBumpOptions,
+    caps: &FeeCapOptions,
+    budget: Option<&SpamBudget>,
+    receipt_timeout: Option<Duration>,
+    mut on_broadcast: B,
+) -> Result<TransactionReceipt, SendError>
+where
+    P: Provider + WalletProvider,
+    D: CallDecoder + Clone,
+    B: FnMut(u64, &[B256]),
+{
+    let provider = call.provider;
+
+    // Pin the nonce, so that resubmissions replace the original transaction.
+    let sender = provider.default_signer_address();
+    let first_send = async {
+        let nonce = provider.get_transaction_count(sender).pending().await?;
+        let call = call.nonce(nonce);
+        let tx = cost.apply(call.clone()).send().await?;
+        Ok::<_, SendError>((nonce, call, tx))
+    };
+    let (nonce, call, tx) = match first_send.await {
+        Ok(sent) => sent,
+        Err(e) => {
+            // Nothing was broadcast, so none of the reserved budget can be spent.
+            if let Some(budget) = budget {
+                budget.settle(cost.total(), U256::ZERO);
+            }
+            return Err(e);
+        }
+    };
+    let mut tx_hashes: Vec<B256> = vec![*tx.tx_hash()];
+    on_broadcast(nonce, &tx_hashes);
+    let mut bumps = 0;
+    let sent_at = Instant::now();
+
+    loop {
+        // Wait for any of the broadcast transactions to be mined, until it's time to bump.
+        //
+        // From now on, RPC errors are retried here rather than returned: the caller could
+        // otherwise send another forced inclusion while one of these can still be mined.
+        let can_bump = bumps < bump.max_fee_bumps;
+        let mut bump_at = None;
+        loop {
+            match find_receipt(provider, &tx_hashes).await {
+                Ok(Some(receipt)) => {
+                    if let Some(budget) = budget {
+                        let outcome = SendOutcome::from_receipt(&receipt, cost.inclusion_fee());
+                        budget.settle(cost.total(), outcome.cost);
+                    }
+                    return Ok(receipt);
+                }
+                Ok(None) => {}
+                Err(e) => println!("⚠️ Failed to check the forced inclusion receipts: {e}"),
+            }
+
+            if let Some(timeout) = receipt_timeout
+                && sent_at.elapsed() >= timeout
+            {
+                let tx_hash = *tx_hashes.last().expect("at least one transaction was sent");
+                return Err(SendError::ReceiptTimeout { tx_hash, timeout });
+            }
+
+            if can_bump {
+                match provider.get_block_number().await {
+                    Ok(block) => {
+                        let bump_at = *bump_at.get_or_insert(block + bump.resubmit_after_blocks);
+                        if block >= bump_at {
+                            break;
+                        }
+                    }
+                    Err(e) => println!("⚠️ Failed to read the L1 block number: {e}"),
+                }
+            }
+
+            sleep(RECEIPT_POLL_INTERVAL).await;
+        }
+
+        let bumped = cost.bumped(bump.fee_bump_percent);
+        let reserved = bumped.check(caps).and_then(|()| match budget {
+            Some(budget) => budget.reserve(bumped.total().saturating_sub(cost.total())),
+            None => Ok(()),
+        });
+        if let Err(e) = reserved {
+            println!("⚠️ Not bumping forced inclusion fees any further: {e}");
+            bumps = bump.max_fee_bumps;
+            continue;
+        }
+
+        bumps += 1;
+        cost = bumped;
+        println!(
+            "⛽ Forced inclusion transaction not mined after {} blocks, resubmitting with nonce {nonce} and bumped fees: max fee {} wei, priority fee {} wei, blob fee {} wei ({bumps}/{})",
+            bump.resubmit_after_blocks,
+            cost.max_fee_per_gas,
+            cost.max_priority_fee_per_gas,
+            cost.max_fee_per_blob_gas,
+            bump.max_fee_bumps,
+        );
+
+        match cost.apply(call.clone()).send().await {
+            Ok(tx) => {
+                tx_hashes.push(*tx.tx_hash());
+                on_broadcast(nonce, &tx_hashes);
+            }
+            // Failed replacements (e.g. when underpriced) are bumped again on the next round.
+            Err(e) => println!("⚠️ Failed to replace forced inclusion transaction: {e}"),
+        }
+    }
+}
+
+/// Returns the receipt of the first mined transaction among `tx_hashes`, newest first.
+async fn find_receipt<P: Provider>(
+    provider: &P,
+    tx_hashes: &[B256],
+) -> Result<Option<TransactionReceipt>, TransportError> {
+    for tx_hash in tx_hashes.iter().rev() {
+        if let Some(receipt) = provider.get_transaction_receipt(*tx_hash).await? {
+            return Ok(Some(receipt));
+        }
+    }
+    Ok(None)
+}
+
+/// Waits for the force-included L2 transactions to be included on L2, until `timeout`.
+pub async fn wait_for_l2_inclusion<P: Provider>(
+    l2: &P,
+    tx_hashes: &[B256],
+    timeout: Duration,
+) -> Result<(), SendError> {
+    let started = Instant::now();
+    for tx_hash in tx_hashes {
+        println!("⏳ Waiting for the L2 inclusion of {tx_hash}");
+        loop {
+            if let Some(receipt) = l2.get_transaction_receipt(*tx_hash).await? {
+                let block = receipt.block_number.unwrap_or_default();
+                println!("✅ L2 tx {tx_hash} included in L2 block {block}");
+                break;
+            }
+
+            if started.elapsed() >= timeout {
+                let tx_hash = *tx_hash;
+                return Err(SendError::L2InclusionTimeout { tx_hash, timeout });
+            }
+            sleep(L2_POLL_INTERVAL).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Send a forced inclusion with `send_one`, unless a previous run was interrupted before the
+/// last one of the L1 signer was mined, in which case it is resumed by `resume_pending` instead
+/// of sending another one.
+///
+/// With `--wait-l2`, wait for the L2 inclusion of the forced inclusion that was sent or
+/// resumed. The forced inclusions of previous runs that weren't interrupted aren't waited for.
+pub async fn send_or_resume<L2, R, S, Fut>(
+    mut opts: SendCmdOptions,
+    l2: Option<&DefaultWalletProvider>,
+    l2_reader: Option<&L2>,
+    state: &StateStore,
+    store: Address,
+    resume_pending: R,
+    send_one: S,
+) -> eyre::Result<()>
+where
+    L2: Provider,
+    R: Future<Output = eyre::Result<Vec<InclusionRecord>>>,
+    S: FnOnce(SendCmdOptions) -> Fut,
+    Fut: Future<Output = eyre::Result<Option<SentInclusion>>>,
+{
+    if opts.dry_run {
+        return send_one(opts).await.map(|_| ());
+    }
+
+    // Dropped or reverted forced inclusions weren't queued, so they don't replace this one.
+    let resumed = resume_pending.await?;
+    let queued = resumed.into_iter().rev().find(|record| {
+        matches!(
+            record.status,
+            InclusionStatus::Queued | InclusionStatus::Processed | InclusionStatus::Included
+        )
+    });
+    let mut record = match queued {
+        Some(record) => {
+            println!(
+                "⏭️ Not sending another forced inclusion from {}",
+                record.l1_sender
+            );
+            record
+        }
+        None => {
+            if let Some(l2) = l2 {
+                let sender = l2.default_signer_address();
+                opts.l2_nonce = Some(resume_l2_nonce(state, store, l2, sender).await?);
+            }
+            match send_one(opts.clone()).await? {
+                Some(sent) => sent.record,
+                None => return Ok(()),
+            }
+        }
+    };
+
+    if opts.wait_l2
+        && matches!(
+            record.status,
+            InclusionStatus::Queued | InclusionStatus::Processed
+        )
+    {
+        let l2 = l2_reader.ok_or(MissingConfigError("L2_RPC_URL"))?;
+        let timeout = Duration::from_secs(opts.wait_l2_timeout_secs);
+        wait_for_l2_inclusion(l2, &record.l2_tx_hashes, timeout).await?;
+        record.status = InclusionStatus::Included;
+        state.save(&record)?;
+    }
+
+    Ok(())
+}
+
+/// Check the forced inclusion `call`, carrying `num_blobs` blobs, against the L1 balance and
+/// the fee caps, and send it unless it's a dry run, reserving its cost from the spam `budget`,
+/// if any. Returns `None` on a dry run.
+///
+/// The forced inclusion is tracked in the state store as `record` from its first broadcast.
+/// Once mined, its queue index is read with `queue_index`, as it's exposed differently by each
+/// fork, and store errors are decoded as `E`.
+pub async fn send_inclusion<E, P, D, F, Fut>(
+    call: CallBuilder<&P, D>,
+    num_blobs: usize,
+    mut record: InclusionRecord,
+    opts: &SendCmdOptions,
+    state: Option<&StateStore>,
+    budget: Option<&SpamBudget>,
+    queue_index: F,
+) -> eyre::Result<Option<SentInclusion>>
+where
+    E: SolInterface + Debug + Into<SendError>,
+    P: Provider + WalletProvider,
+    D: CallDecoder + Clone,
+    F: FnOnce(TransactionReceipt) -> Fut,
+    Fut: Future<Output = eyre::Result<Option<u64>>>,
+{
+    let provider = call.provider;
+    let fee_gwei = (record.fee_wei / U256::from(GWEI_TO_WEI)).saturating_to::<u64>();
+
+    // Check that the L1 signer can afford the fee and blob gas before estimating the gas,
+    // which fails with a bare RPC error otherwise
+    let cost = InclusionCost::fetch(provider, fee_gwei, num_blobs).await?;
+    check_balance(provider, "L1", record.l1_sender, cost.total()).await?;
+
+    // Check the L1 transaction cost against the fee caps
+    let cost = cost.estimate_gas(&call).await?;
+    cost.check(&opts.fee_caps)?;
+    println!(
+        "💰 Forced inclusion fee: {fee_gwei} gwei, blob base fee: {} wei, max total cost: {} ETH",
+        cost.blob_base_fee,
+        format_ether(cost.total())
+    );
+
+    if opts.dry_run {
+        println!("✅ Pre-flight checks passed, not sending the forced inclusion (dry run)");
+        return Ok(None);
+    }
+
+    // Track the forced inclusion in the state store from its first broadcast
+    let on_broadcast = |nonce, tx_hashes: &[B256]| {
+        record.l1_nonce = nonce;
+        record.l1_tx_hashes = tx_hashes.to_vec();
+        if let Some(state) = state {
+            state.save_or_warn(&record);
+        }
+    };
+
+    // Send the forced inclusion transaction on L1
+    let timeout = opts.receipt_timeout_secs.map(Duration::from_secs);
+    let fee_bump = &opts.fee_bump;
+    let caps = &opts.fee_caps;
+    if let Some(budget) = budget {
+        budget.reserve(cost.total())?;
+    }
+    let receipt = send_with_fee_bumps(call, cost, fee_bump, caps, budget, timeout, on_broadcast)
+        .await
+        .map_err(SendError::decode::<E>)?;
+
+    let outcome = SendOutcome::from_receipt(&receipt, record.fee_wei);
+    let mut index = None;
+    if receipt.status() {
+        index = queue_index(receipt.clone()).await.unwrap_or_else(|e| {
+            println!("⚠️ Failed to find the queue index of the forced inclusion: {e}");
+            None
+        });
+    }
+    record.mined(&receipt, index);
+    if let Some(state) = state {
+        state.save_or_warn(&record);
+    }
+
+    if !receipt.status() {
+        let err = SendError::reverted::<E, _>(provider, &receipt, outcome.cost);
+        return Err(err.await.into());
+    }
+
+    println!(
+        "✅ Forced inclusion batch sent successfully! Hash: {}",
+        receipt.transaction_hash
+    );
+    Ok(Some(SentInclusion {
+        receipt,
+        outcome,
+        record,
+    }))
+}
+
+/// Resolve the L2 transaction to be force-included: the pre-signed one, if any, or else a
+/// transfer signed by the L2 signer. Returns it with its L2 signer, unless it was pre-signed.
+pub async fn resolve_l2_tx(
+    opts: &SendCmdOptions,
+    l2: Option<&DefaultWalletProvider>,
+) -> eyre::Result<(TxEnvelope, Option<Address>)> {
+    let l2_tx = match (&opts.l2_raw_tx, l2) {
+        (Some(raw), _) => TxEnvelope::decode_2718(&mut raw.as_ref())
+            .wrap_err("failed to decode the pre-signed L2 transaction")?,
+        (None, Some(l2)) => build_l2_tx(opts, l2).await?,
+        (None, None) => return Err(MissingConfigError("an L2 signer or --l2-raw-tx").into()),
+    };
+    println!(
+        "🔍 L2 tx to be force-included: nonce={}, hash={}",
+        l2_tx.nonce(),
+        l2_tx.hash()
+    );
+    let l2_sender = l2
+        .filter(|_| opts.l2_raw_tx.is_none())
+        .map(|l2| l2.default_signer_address());
+    Ok((l2_tx, l2_sender))
+}
+
+/// Build and sign the L2 transaction to be force-included, checking that the L2 signer
+/// can pay for it.
+async fn build_l2_tx(
+    opts: &SendCmdOptions,
+    l2: &DefaultWalletProvider,
+) -> eyre::Result<TxEnvelope> {
+    // Generate the L2 transaction to be force-included. Make it a simple transfer of 1 gwei.
+    let mut l2_tx_req = TransactionRequest::default()
+        .to(Address::ZERO)
+        .value(U256::from(GWEI_TO_WEI));
+
+    // If a nonce or nonce delta is provided, calculate the nonce manually instead of using the
+    // default `CachedNonceManager` value.
+    let sender = l2.default_signer_address();
+    if opts.l2_nonce.is_some() || opts.nonce_delta > 0 {
+        let nonce = match opts.l2_nonce {
+            Some(nonce) => nonce,
+            None => l2.get_transaction_count(sender).pending().await?,
+        };
+        l2_tx_req.set_nonce(nonce + opts.nonce_delta);
+    }
+
+    let l2_tx = l2.fill(l2_tx_req).await?.try_into_envelope()?;
+
+    // Check that the L2 signer can pay for the force-included transaction
+    let l2_cost =
+        l2_tx.value() + U256::from(l2_tx.gas_limit()) * U256::from(l2_tx.max_fee_per_gas());
+    check_balance(l2, "L2", sender, l2_cost).await?;
+
+    Ok(l2_tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use alloy::{
+        primitives::address,
+        providers::{ProviderBuilder, mock::Asserter},
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::state::resume;
+
+    const STORE: Address = address!("0x00000000000000000000000000000000000000f1");
+    const L1_SENDER: Address = address!("0x00000000000000000000000000000000000000a1");
+
+    /// Stands in for a fork's `send_one`, saving a queued forced inclusion without an L2 tx.
+    async fn send_queued(
+        state: &StateStore,
+        sends: &Cell<u64>,
+    ) -> eyre::Result<Option<SentInclusion>> {
+        let nonce = sends.get();
+        sends.set(nonce + 1);
+
+        let receipt: TransactionReceipt = serde_json::from_value(json!({
+            "transactionHash": B256::with_last_byte(nonce as u8 + 1),
+            "transactionIndex": "0x0",
+            "blockHash": B256::ZERO,
+            "blockNumber": "0x1",
+            "from": L1_SENDER,
+            "to": STORE,
+            "contractAddress": null,
+            "gasUsed": "0x5208",
+            "cumulativeGasUsed": "0x5208",
+            "effectiveGasPrice": "0x1",
+            "logs": [],
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "status": "0x1",
+            "type": "0x3",
+        }))?;
+        let mut record =
+            InclusionRecord::new(STORE, L1_SENDER, None, 0, vec![], vec![], U256::ZERO);
+        record.l1_nonce = nonce;
+        record.mined(&receipt, Some(nonce));
+        state.save(&record)?;
+
+        let outcome = SendOutcome::from_receipt(&receipt, U256::ZERO);
+        Ok(Some(SentInclusion {
+            receipt,
+            outcome,
+            record,
+        }))
+    }
+
+    #[tokio::test]
+    async fn sends_again_with_wait_l2_after_a_finished_send() {
+        let path = std::env::temp_dir().join(format!("fi-toolbox-send-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let state = StateStore::open(&path).unwrap();
+        // Nothing is pending, so resuming doesn't make any RPC call.
+        let provider = ProviderBuilder::new().connect_mocked_client(Asserter::new());
+        let resume_pending = || {
+            let queue_index = |_| async { Ok(None) };
+            resume(
+                &state,
+                STORE,
+                L1_SENDER,
+                &provider,
+                Some(&provider),
+                0,
+                None,
+                queue_index,
+            )
+        };
+        let sends = Cell::new(0);
+
+        let opts = SendCmdOptions::default();
+        let send = |_| send_queued(&state, &sends);
+        send_or_resume(
+            opts,
+            None,
+            Some(&provider),
+            &state,
+            STORE,
+            resume_pending(),
+            send,
+        )
+        .await
+        .unwrap();
+
+        // The queued forced inclusion of the finished run doesn't replace this one.
+        let opts = SendCmdOptions {
+            wait_l2: true,
+            ..Default::default()
+        };
+        let send = |_| send_queued(&state, &sends);
+        send_or_resume(
+            opts,
+            None,
+            Some(&provider),
+            &state,
+            STORE,
+            resume_pending(),
+            send,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(sends.get(), 2);
+        let records = state.list(STORE).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].status, InclusionStatus::Queued);
+        assert_eq!(records[1].status, InclusionStatus::Included);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -30,6 +30,7 @@ sol! {
 
         /// @notice Represents a frame of data that is stored in multiple blobs. Note the size is
         /// encoded as a bytes32 at the offset location.
+        #[derive(serde::Serialize, serde::Deserialize)]
         struct BlobSlice {
             /// @notice The blobs containing the proposal's content.
             bytes32[] blobHashes;
@@ -80,6 +81,7 @@ sol! {
     #[derive(Debug)]
     interface IForcedInclusionStore {
         /// @notice Represents a forced inclusion that will be stored onchain.
+        #[derive(serde::Serialize, serde::Deserialize)]
         struct ForcedInclusion {
             /// @notice The fee in Gwei that was paid to submit the forced inclusion.
             uint64 feeInGwei;
@@ -90,6 +92,15 @@ sol! {
         /// @dev Event emitted when a forced inclusion is stored.
         event ForcedInclusionSaved(ForcedInclusion forcedInclusion);
 
+        /// @dev Error thrown when the paid fee is lower than the current forced inclusion fee.
+        error InsufficientFee();
+        /// @dev Error thrown when the paid fee doesn't match the forced inclusion fee.
+        error IncorrectFee();
+        /// @dev Error thrown by LibBlobs when a blob is not found.
+        error BlobNotFound();
+        /// @dev Error thrown by LibBlobs when the blob reference has no blobs.
+        error NoBlobs();
+
         /// @notice Saves a forced inclusion request
         /// A priority fee must be paid to the contract
         /// @param _blobReference The blob locator that contains the transaction data
@@ -1,77 +1,137 @@
 mod chainio;
 
-use std::time::Duration;
+use std::{ops::Range, time::Duration};
 
 use alloy::{
     consensus::{Transaction, constants::GWEI_TO_WEI},
-    network::TransactionBuilder,
-    primitives::{Address, U256, aliases::U24},
-    providers::{Provider, ProviderBuilder, WalletProvider, fillers::BlobGasEstimator},
-    rpc::types::TransactionRequest,
+    eips::eip7594::BlobTransactionSidecarVariant,
+    primitives::{
+        Address, B256, U256,
+        aliases::{U24, U48},
+        utils::format_ether,
+    },
+    providers::{Provider, WalletProvider},
+    rpc::types::TransactionReceipt,
+    sol_types::SolEvent,
 };
+use eyre::WrapErr;
 use futures::StreamExt;
 use taiko_protocol::shasta::manifest::{BlockManifest, DerivationSourceManifest};
-use tokio::time::sleep;
 
 use crate::{
-    blob::create_blob_sidecar_from_data_async,
+    blob::{BlobEncoder, resolve_sidecar_version},
+    cancel::cancel_pending,
     cli::{
-        Cmd::{MonitorQueue, ReadQueue, Send, Spam},
-        SendCmdOptions, SpamCmdOptions,
+        Cmd::{Cancel, DiffQueue, MonitorQueue, Preflight, ReadQueue, Send, Spam, Status},
+        MissingConfigError, ReadQueueOptions, SendCmdOptions, SpamCmdOptions,
     },
-    wallet_provider::DefaultWalletProvider,
+    fees::wait_for_fee,
+    preflight::check_connectivity,
+    queue::{self, QueueSnapshot, SnapshotEntry, diff_queue},
+    send::{SendError, SentInclusion, resolve_l2_tx, send_inclusion, send_or_resume},
+    spam::{SpamBudget, SpamRun},
+    state::{InclusionRecord, StateStore, refresh, resume, saved_in_block},
+    wallet_provider::{DefaultProvider, DefaultWalletProvider, SpamAccount},
 };
 
-use chainio::IForcedInclusionStore::{self, ForcedInclusionSaved, IForcedInclusionStoreInstance};
+use chainio::IForcedInclusionStore::{
+    self, ForcedInclusionSaved, IForcedInclusionStoreErrors, IForcedInclusionStoreInstance,
+};
 use chainio::LibBlobs::BlobReference;
 
 pub async fn handle_command(cli: crate::cli::Cli) -> eyre::Result<()> {
-    // Use a scaled blob gas estimator with 20% buffer to ensure we're above current fee
-    let blob_gas_estimator = BlobGasEstimator::scaled(120);
-    let l1 = ProviderBuilder::new()
-        .wallet(cli.l1_private_key)
-        .with_blob_gas_estimator(blob_gas_estimator)
-        .connect_http(cli.l1_rpc_url);
-    let l2 = ProviderBuilder::new()
-        .wallet(cli.l2_private_key)
-        .connect_http(cli.l2_rpc_url);
-
-    let store = IForcedInclusionStore::new(cli.forced_inclusion_store_address, l1);
-
-    match cli.command {
-        // shasta commands
-        ReadQueue => read_queue(&store).await,
-        MonitorQueue => monitor_queue(&store).await,
-        Send(opts) => send_one(opts, &l2, &store).await,
-        Spam(opts) => spam(opts, &l2, &store).await,
+    let address = cli.forced_inclusion_store_address;
+    let reader = || IForcedInclusionStore::new(address, cli.l1_provider());
+    let writer = || {
+        eyre::Ok(IForcedInclusionStore::new(
+            address,
+            cli.l1_wallet_provider()?,
+        ))
+    };
+    let encoder = || BlobEncoder::new(cli.blob_encoder);
+
+    match &cli.command {
+        // read-only commands, which don't need any signer
+        ReadQueue(opts) => read_queue(&reader(), opts).await,
+        MonitorQueue => monitor_queue(&reader()).await,
+        DiffQueue(opts) => diff_queue(&opts.before, &opts.after),
+        Status => {
+            let state = StateStore::open(&cli.state_db)?;
+            status(&reader(), cli.l2_provider().as_ref(), &state).await
+        }
+        Send(opts) => {
+            // A pre-signed L2 transaction doesn't need the L2 signer.
+            let l2 = match opts.l2_raw_tx {
+                Some(_) => None,
+                None => Some(cli.l2_wallet_provider()?),
+            };
+            let l2_reader = cli.l2_provider();
+            if opts.wait_l2 && l2_reader.is_none() {
+                return Err(MissingConfigError("L2_RPC_URL").into());
+            }
+            let state = StateStore::open(&cli.state_db)?;
+            let (store, encoder) = (writer()?, encoder()?);
+            let l2 = l2.as_ref();
+            send(
+                opts.clone(),
+                l2,
+                l2_reader.as_ref(),
+                &store,
+                &encoder,
+                &state,
+            )
+            .await
+        }
+        Spam(opts) => {
+            let accounts = cli.spam_accounts(opts)?;
+            let state = StateStore::open(&cli.state_db)?;
+            spam(opts.clone(), accounts, address, &encoder()?, &state).await
+        }
+        Cancel(opts) => cancel_pending(*opts, writer()?.provider(), &encoder()?).await,
+        Preflight => preflight(&cli.l2_wallet_provider()?, &writer()?, &encoder()?).await,
     }
 }
 
-/// Send a forced inclusion transaction.
-pub async fn send_one<P: Provider>(
+/// Send a forced inclusion, or resume the one of an interrupted run, see [`send_or_resume`].
+pub async fn send<P: Provider + WalletProvider>(
     opts: SendCmdOptions,
-    l2: &DefaultWalletProvider,
+    l2: Option<&DefaultWalletProvider>,
+    l2_reader: Option<&DefaultProvider>,
     store: &IForcedInclusionStoreInstance<P>,
+    encoder: &BlobEncoder,
+    state: &StateStore,
 ) -> eyre::Result<()> {
-    // Generate the L2 transaction to be force-included. Make it a simple transfer of 1 gwei.
-    let mut l2_tx_req = TransactionRequest::default()
-        .to(Address::ZERO)
-        .value(U256::from(GWEI_TO_WEI));
-
-    // If a nonce delta is provided, calculate the nonce manually instead of using the
-    // default `CachedNonceManager` value.
-    if opts.nonce_delta > 0 {
-        let sender = l2.wallet().default_signer().address();
-        let pending_nonce = l2.get_transaction_count(sender).pending().await?;
-        l2_tx_req.set_nonce(pending_nonce + opts.nonce_delta);
+    let timeout = opts.receipt_timeout_secs.map(Duration::from_secs);
+    let resumed = resume_pending(store, l2_reader, state, timeout);
+    let send = |opts| send_one(opts, l2, store, encoder, Some(state), None);
+    send_or_resume(opts, l2, l2_reader, state, *store.address(), resumed, send).await
+}
+
+/// Send a forced inclusion transaction, reserving its cost from the spam `budget`, if any.
+/// Returns `None` on a dry run.
+pub async fn send_one<P: Provider + WalletProvider>(
+    opts: SendCmdOptions,
+    l2: Option<&DefaultWalletProvider>,
+    store: &IForcedInclusionStoreInstance<P>,
+    encoder: &BlobEncoder,
+    state: Option<&StateStore>,
+    budget: Option<&SpamBudget>,
+) -> eyre::Result<Option<SentInclusion>> {
+    // Wait for a cheaper fee window, if requested
+    if let Some(target_gwei) = opts.wait_for_fee {
+        wait_for_fee(
+            store.provider(),
+            target_gwei,
+            Duration::from_secs(opts.wait_timeout_secs),
+            Duration::from_secs(opts.fee_poll_interval_secs),
+            || async { Ok(store.getCurrentForcedInclusionFee().call().await?) },
+        )
+        .await?;
     }
 
-    let l2_tx = l2.fill(l2_tx_req).await?.try_into_envelope()?;
-    println!(
-        "🔍 L2 tx to be force-included: nonce={}, hash={}",
-        l2_tx.nonce(),
-        l2_tx.hash()
-    );
+    let (l2_tx, l2_sender) = resolve_l2_tx(&opts, l2).await?;
+    let l2_nonce = l2_tx.nonce();
+    let l2_tx_hashes = vec![*l2_tx.hash()];
 
     // Build the proposal manifest.
     let block_manifests = vec![BlockManifest {
@@ -89,65 +149,303 @@ pub async fn send_one<P: Provider>(
     let manifest_data = manifest.encode_and_compress()?;
 
     // Prepare the sidecar for the forced inclusion
-    let sidecar = create_blob_sidecar_from_data_async(manifest_data.into()).await?;
+    let sidecar_version =
+        resolve_sidecar_version(opts.blob_sidecar_version, store.provider()).await?;
+    let sidecar = encoder
+        .encode(manifest_data.into(), sidecar_version)
+        .await?;
+    let blob_hashes: Vec<B256> = sidecar.versioned_hashes().collect();
 
     // Get the required fee for the forced inclusion
-    let fee_wei = U256::from(store.getCurrentForcedInclusionFee().call().await? * GWEI_TO_WEI);
+    let fee_gwei = store.getCurrentForcedInclusionFee().call().await?;
+    let fee_wei = U256::from(fee_gwei * GWEI_TO_WEI);
 
+    let num_blobs = sidecar.blobs().len();
     let blob_ref = BlobReference {
         blobStartIndex: 0,
-        numBlobs: sidecar.blobs.len() as u16,
+        numBlobs: num_blobs as u16,
         offset: U24::ZERO,
     };
 
-    // Send the forced inclusion transaction on L1
-    match store
-        .saveForcedInclusion(blob_ref)
-        .sidecar(sidecar)
-        .value(fee_wei)
-        .send()
-        .await
+    let call = store.saveForcedInclusion(blob_ref).value(fee_wei);
+    let call = match sidecar {
+        BlobTransactionSidecarVariant::Eip4844(sidecar) => call.sidecar(sidecar),
+        BlobTransactionSidecarVariant::Eip7594(sidecar) => call.sidecar_7594(sidecar),
+    };
+
+    let record = InclusionRecord::new(
+        *store.address(),
+        store.provider().default_signer_address(),
+        l2_sender,
+        l2_nonce,
+        l2_tx_hashes,
+        blob_hashes,
+        fee_wei,
+    );
+    let queue_index =
+        |receipt: TransactionReceipt| async move { find_queue_index(store, &receipt).await };
+    let sent = send_inclusion::<IForcedInclusionStoreErrors, _, _, _, _>(
+        call,
+        num_blobs,
+        record,
+        &opts,
+        state,
+        budget,
+        queue_index,
+    )
+    .await?;
+
+    if let Some(sent) = &sent
+        && let Err(e) = print_queue_position(store, &sent.receipt, sent.record.queue_index).await
     {
-        Ok(tx) => {
-            let receipt = tx.get_receipt().await?;
-            if receipt.status() {
-                println!(
-                    "✅ Forced inclusion batch sent successfully! Hash: {}",
-                    receipt.transaction_hash
-                );
-            } else {
-                println!(
-                    "❌ Forced inclusion batch failed! Status: {}",
-                    receipt.transaction_hash
-                );
-            }
-        }
-        Err(e) => {
-            println!("❌ Forced inclusion batch failed! Error: {e}",);
-        }
+        println!("⚠️ Failed to read the queue position of the forced inclusion: {e}");
+    }
+    Ok(sent)
+}
+
+/// Compute the queue index of the forced inclusion saved by `receipt`, from the queue tail
+/// at its block.
+async fn find_queue_index<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+    receipt: &TransactionReceipt,
+) -> eyre::Result<Option<u64>> {
+    let event = ForcedInclusionSaved::SIGNATURE_HASH;
+    let l1 = store.provider();
+    let Some((block, later)) = saved_in_block(l1, receipt, *store.address(), event).await? else {
+        return Ok(None);
+    };
+
+    let state = store
+        .getForcedInclusionState()
+        .block(block.into())
+        .call()
+        .await?;
+    let tail = state.tail_.to::<u64>();
+    Ok(tail.checked_sub(later + 1))
+}
+
+/// Print the queue index and position of the forced inclusion saved by `receipt`, and the
+/// fee it paid, from its `ForcedInclusionSaved` event.
+async fn print_queue_position<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+    receipt: &TransactionReceipt,
+    queue_index: Option<u64>,
+) -> eyre::Result<()> {
+    let saved = receipt
+        .logs()
+        .iter()
+        .filter(|log| log.address() == *store.address())
+        .find_map(|log| log.log_decode::<ForcedInclusionSaved>().ok())
+        .ok_or_else(|| eyre::eyre!("no ForcedInclusionSaved event in the receipt"))?;
+    let fee_gwei = saved.inner.data.forcedInclusion.feeInGwei;
+    let fee_paid = format_ether(U256::from(fee_gwei * GWEI_TO_WEI));
+
+    let Some(index) = queue_index else {
+        println!("📥 Forced inclusion saved at an unknown queue index, fee paid: {fee_paid} ETH");
+        return Ok(());
+    };
+    let head = queue_head(store).await?;
+    match index.checked_sub(head) {
+        Some(ahead) => println!(
+            "📥 Forced inclusion queued at index {index}, {ahead} forced inclusions ahead of it, fee paid: {fee_paid} ETH"
+        ),
+        None => println!(
+            "📥 Forced inclusion saved at index {index}, already processed (queue head: {head}), fee paid: {fee_paid} ETH"
+        ),
     }
     Ok(())
 }
 
-/// Read the forced inclusion queue from the contract.
-pub async fn read_queue<P: Provider>(store: &IForcedInclusionStoreInstance<P>) -> eyre::Result<()> {
+/// Resume the forced inclusions of the L1 signer left pending by a previous run, see [`resume`].
+async fn resume_pending<P, L2>(
+    store: &IForcedInclusionStoreInstance<P>,
+    l2: Option<&L2>,
+    state: &StateStore,
+    timeout: Option<Duration>,
+) -> eyre::Result<Vec<InclusionRecord>>
+where
+    P: Provider + WalletProvider,
+    L2: Provider,
+{
+    let l1 = store.provider();
+    let l1_sender = l1.default_signer_address();
+    let head = queue_head(store).await?;
+    resume(
+        state,
+        *store.address(),
+        l1_sender,
+        l1,
+        l2,
+        head,
+        timeout,
+        |receipt| async move { find_queue_index(store, &receipt).await },
+    )
+    .await
+}
+
+/// Read the index of the oldest forced inclusion in the queue.
+pub async fn queue_head<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+) -> eyre::Result<u64> {
     let state = store.getForcedInclusionState().call().await?;
+    Ok(state.head_.to::<u64>())
+}
+
+/// Refresh the forced inclusions sent to the store from the state store, and list them.
+pub async fn status<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+    l2: Option<&DefaultProvider>,
+    state: &StateStore,
+) -> eyre::Result<()> {
+    let mut records = state.list(*store.address())?;
+    if records.is_empty() {
+        println!("No forced inclusions sent to {} yet", store.address());
+        return Ok(());
+    }
+
+    let head = queue_head(store).await?;
+    let l1 = store.provider();
+    refresh(state, &mut records, l1, l2, head, |receipt| async move {
+        find_queue_index(store, &receipt).await
+    })
+    .await?;
+
+    for record in &records {
+        println!("Forced inclusion {record}");
+    }
+    Ok(())
+}
+
+impl From<IForcedInclusionStoreErrors> for SendError {
+    fn from(err: IForcedInclusionStoreErrors) -> Self {
+        match err {
+            IForcedInclusionStoreErrors::InsufficientFee(_)
+            | IForcedInclusionStoreErrors::IncorrectFee(_) => Self::FeeTooLow,
+            IForcedInclusionStoreErrors::BlobNotFound(_) => Self::BlobNotFound,
+            IForcedInclusionStoreErrors::NoBlobs(_) => Self::InvalidParams,
+        }
+    }
+}
+
+/// Validate the configuration, and check that both signers can afford a forced inclusion.
+pub async fn preflight<P: Provider + WalletProvider>(
+    l2: &DefaultWalletProvider,
+    store: &IForcedInclusionStoreInstance<P>,
+    encoder: &BlobEncoder,
+) -> eyre::Result<()> {
+    check_connectivity(store.provider(), l2, *store.address()).await?;
+    println!(
+        "🔑 L1 signer: {}, L2 signer: {}",
+        store.provider().default_signer_address(),
+        l2.default_signer_address()
+    );
+
+    // The store of another fork doesn't expose the same fee getter.
+    let fee_gwei = store
+        .getCurrentForcedInclusionFee()
+        .call()
+        .await
+        .wrap_err("failed to read the forced inclusion fee, is FORK set correctly?")?;
+    println!("✅ Forced inclusion fee: {fee_gwei} gwei");
+
+    // Estimate a full forced inclusion, without sending it.
+    let opts = SendCmdOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+    send_one(opts, Some(l2), store, encoder, None, None).await?;
+
+    Ok(())
+}
+
+/// Read the forced inclusion queue from the contract, page by page.
+pub async fn read_queue<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+    opts: &ReadQueueOptions,
+) -> eyre::Result<()> {
+    let (block_number, block) = queue::resolve_block(store.provider(), opts.block).await?;
+    let state = store.getForcedInclusionState().block(block).call().await?;
     let head = state.head_.to::<u64>();
-    let size = state.tail_.saturating_sub(state.head_);
+    let tail = state.tail_.to::<u64>();
 
-    if size == 0 {
+    // An empty queue is still saved to the snapshot.
+    let pages = queue::pages(head, tail, opts);
+    if head == tail {
         println!("Forced inclusion queue is empty");
+    } else if pages.is_empty() {
+        println!("No forced inclusions to read in the queue [{head}, {tail})");
         return Ok(());
     }
 
-    let forced_inclusions = store.getForcedInclusions(state.head_, size).call().await?;
-    for (i, fi) in forced_inclusions.iter().enumerate() {
-        println!("Forced inclusion {}: {:?}\n", head + i as u64, fi);
+    let fetch_page = |range: Range<u64>| async move {
+        let count = U48::from(range.end - range.start);
+        let page = store
+            .getForcedInclusions(U48::from(range.start), count)
+            .block(block)
+            .call()
+            .await?;
+        eyre::Ok(range.zip(page).collect::<Vec<_>>())
+    };
+    let mut pages = queue::fetch_pages(pages, opts.concurrency, fetch_page);
+
+    let (mut read, mut matched) = (0, 0);
+    let mut snapshot = Vec::new();
+    while let Some(page) = pages.next().await {
+        let mut page = page?;
+        if opts.reverse {
+            page.reverse();
+        }
+
+        for (i, fi) in page {
+            read += 1;
+            let created_at = fi.blobSlice.timestamp.to::<u64>();
+            if queue::matches(
+                &opts.filter,
+                &fi.blobSlice.blobHashes,
+                fi.feeInGwei,
+                created_at,
+            ) {
+                matched += 1;
+                println!("Forced inclusion {i}: {fi:?}\n");
+            }
+            if opts.snapshot.is_some() {
+                snapshot.push(SnapshotEntry::new(i, &fi)?);
+            }
+        }
+    }
+
+    if queue::is_filtered(&opts.filter) {
+        println!("{matched} of the {read} forced inclusions read matched the filters");
+    }
+
+    if let Some(path) = &opts.snapshot {
+        let fee_in_gwei = store
+            .getCurrentForcedInclusionFee()
+            .block(block)
+            .call()
+            .await?;
+        let snapshot = QueueSnapshot {
+            store: *store.address(),
+            block_number,
+            head,
+            tail,
+            fee_in_gwei,
+            forced_inclusions: snapshot,
+        };
+        snapshot.save(path)?;
     }
 
     Ok(())
 }
 
+/// Read the number of forced inclusions in the queue.
+pub async fn queue_size<P: Provider>(
+    store: &IForcedInclusionStoreInstance<P>,
+) -> eyre::Result<u64> {
+    let state = store.getForcedInclusionState().call().await?;
+    Ok(state.tail_.saturating_sub(state.head_).to::<u64>())
+}
+
 /// Monitor events in the forced inclusion queue
 pub async fn monitor_queue<P: Provider>(
     store: &IForcedInclusionStoreInstance<P>,
@@ -169,22 +467,63 @@ pub async fn monitor_queue<P: Provider>(
     }
 }
 
-/// Send forced inclusion transactions in a loop.
-pub async fn spam<P: Provider>(
-    opts: SpamCmdOptions,
-    l2: &DefaultWalletProvider,
-    store: &IForcedInclusionStoreInstance<P>,
+/// Send forced inclusion transactions in a loop, concurrently from each spam account.
+pub async fn spam(
+    mut opts: SpamCmdOptions,
+    accounts: Vec<SpamAccount>,
+    address: Address,
+    encoder: &BlobEncoder,
+    state: &StateStore,
 ) -> eyre::Result<()> {
-    let send_opts = SendCmdOptions::default();
+    let accounts: Vec<_> = accounts
+        .into_iter()
+        .map(|account| (IForcedInclusionStore::new(address, account.l1), account.l2))
+        .collect();
 
-    loop {
-        // NOTE: by using the default `CachedNonceManager`, the nonce will be incremented
-        // automatically by the provider without making new RPC calls.
-        if let Err(e) = send_one(send_opts, l2, store).await {
-            eprintln!("Error sending forced-inclusion: {e:?}");
-            return Err(e);
-        }
+    // Resolve the sidecar format once instead of probing the L1 on every send.
+    let (first_store, _) = accounts.first().expect("at least one spam account");
+    opts.blob_sidecar_version =
+        resolve_sidecar_version(opts.blob_sidecar_version, first_store.provider()).await?;
+
+    if accounts.len() > 1 {
+        println!(
+            "🚀 Spamming forced inclusions from {} accounts",
+            accounts.len()
+        );
+    }
+
+    // The schedule and stats are shared by all accounts, which run concurrently on this task.
+    let run = SpamRun::new(&opts, state);
+    let timeout = opts.receipt_timeout_secs.map(Duration::from_secs);
+    let workers = accounts.iter().map(|(store, l2)| {
+        run.account(
+            store.provider(),
+            l2,
+            *store.address(),
+            resume_pending(store, Some(l2), state, timeout),
+            || queue_size(store),
+            |opts| send_one(opts, Some(l2), store, encoder, Some(state), run.budget()),
+        )
+    });
+    run.run(workers).await
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::sol_types::{SolError, SolInterface};
+
+    use super::*;
+    use chainio::LibBlobs;
+
+    #[test]
+    fn decodes_store_and_blob_errors() {
+        // LibBlobs errors revert from the store with the same selectors.
+        let data = LibBlobs::BlobNotFound {}.abi_encode();
+        let err = IForcedInclusionStoreErrors::abi_decode(&data).unwrap();
+        assert!(matches!(SendError::from(err), SendError::BlobNotFound));
 
-        sleep(Duration::from_secs(opts.interval_secs)).await;
+        let data = IForcedInclusionStore::InsufficientFee {}.abi_encode();
+        let err = IForcedInclusionStoreErrors::abi_decode(&data).unwrap();
+        assert!(matches!(SendError::from(err), SendError::FeeTooLow));
     }
 }
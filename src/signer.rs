@@ -0,0 +1,321 @@
+use std::path::{Path, PathBuf};
+
+use alloy::{
+    network::EthereumWallet,
+    primitives::B512,
+    signers::local::{LocalSignerError, MnemonicBuilder, PrivateKeySigner, coins_bip39::English},
+};
+
+use crate::{cli::SignerOptions, remote_signer::RemoteSigner};
+
+/// An error returned when a signer can't be loaded from the configuration.
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+pub enum SignerError {
+    #[error(
+        "no {0} signer configured: set a private key, a keystore, a remote signer or a mnemonic"
+    )]
+    Missing(&'static str),
+    #[error("failed to read the {layer} keystore password")]
+    Password {
+        layer: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to decrypt the {layer} keystore {}", .path.display())]
+    Keystore {
+        layer: &'static str,
+        path: PathBuf,
+        #[source]
+        source: LocalSignerError,
+    },
+    #[error("failed to derive the {layer} signer from the mnemonic")]
+    Mnemonic {
+        layer: &'static str,
+        #[source]
+        source: LocalSignerError,
+    },
+    #[error("deriving {0} accounts requires a mnemonic")]
+    NoMnemonic(u32),
+    #[error("failed to read the {layer} keys file {}", .path.display())]
+    KeysFile {
+        layer: &'static str,
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid private key on line {line} of the {layer} keys file {}", .path.display())]
+    InvalidKey {
+        layer: &'static str,
+        path: PathBuf,
+        line: usize,
+    },
+    #[error("the {layer} keys file {} has no keys", .path.display())]
+    EmptyKeysFile { layer: &'static str, path: PathBuf },
+    #[error("{l1} L1 signers need as many L2 signers, but only {l2} are configured")]
+    TooFewL2Signers { l1: usize, l2: usize },
+}
+
+impl SignerOptions {
+    /// Loads the L1 signer wallet from its private key, keystore, remote signer or the
+    /// mnemonic, in this order.
+    pub fn l1_wallet(&self) -> Result<EthereumWallet, SignerError> {
+        self.load(
+            "L1",
+            self.l1_private_key.as_ref(),
+            self.l1_keystore.as_deref(),
+            self.l1_keystore_password_file.as_deref(),
+            self.l1_remote_signer_public_key,
+            self.l1_mnemonic_index,
+        )
+    }
+
+    /// Loads the L2 signer wallet from its private key, keystore, remote signer or the
+    /// mnemonic, in this order.
+    pub fn l2_wallet(&self) -> Result<EthereumWallet, SignerError> {
+        self.load(
+            "L2",
+            self.l2_private_key.as_ref(),
+            self.l2_keystore.as_deref(),
+            self.l2_keystore_password_file.as_deref(),
+            self.l2_remote_signer_public_key,
+            self.l2_mnemonic_index,
+        )
+    }
+
+    /// Loads the L1 signer wallets to rotate across: from `keys_file` if set, else `accounts`
+    /// consecutive accounts derived from the mnemonic if more than one, else the L1 signer.
+    pub fn l1_wallets(
+        &self,
+        keys_file: Option<&Path>,
+        accounts: u32,
+    ) -> Result<Vec<EthereumWallet>, SignerError> {
+        self.load_many("L1", keys_file, accounts, self.l1_mnemonic_index, || {
+            self.l1_wallet()
+        })
+    }
+
+    /// Loads the L2 signer wallets to rotate across: from `keys_file` if set, else `accounts`
+    /// consecutive accounts derived from the mnemonic if more than one, else the L2 signer.
+    pub fn l2_wallets(
+        &self,
+        keys_file: Option<&Path>,
+        accounts: u32,
+    ) -> Result<Vec<EthereumWallet>, SignerError> {
+        self.load_many("L2", keys_file, accounts, self.l2_mnemonic_index, || {
+            self.l2_wallet()
+        })
+    }
+
+    fn load_many(
+        &self,
+        layer: &'static str,
+        keys_file: Option<&Path>,
+        accounts: u32,
+        first_index: u32,
+        single: impl FnOnce() -> Result<EthereumWallet, SignerError>,
+    ) -> Result<Vec<EthereumWallet>, SignerError> {
+        if let Some(path) = keys_file {
+            let keys = read_keys_file(layer, path)?;
+            return Ok(keys.into_iter().map(Into::into).collect());
+        }
+
+        if accounts > 1 {
+            let phrase = self
+                .mnemonic
+                .as_ref()
+                .ok_or(SignerError::NoMnemonic(accounts))?;
+            return (first_index..first_index + accounts)
+                .map(|index| {
+                    derive_from_mnemonic(phrase, &self.mnemonic_derivation_path, index)
+                        .map(Into::into)
+                        .map_err(|source| SignerError::Mnemonic { layer, source })
+                })
+                .collect();
+        }
+
+        Ok(vec![single()?])
+    }
+
+    fn load(
+        &self,
+        layer: &'static str,
+        private_key: Option<&PrivateKeySigner>,
+        keystore: Option<&Path>,
+        password_file: Option<&Path>,
+        remote_signer_public_key: Option<B512>,
+        mnemonic_index: u32,
+    ) -> Result<EthereumWallet, SignerError> {
+        if let Some(signer) = private_key {
+            return Ok(signer.clone().into());
+        }
+
+        if let Some(path) = keystore {
+            let password = keystore_password(layer, path, password_file)
+                .map_err(|source| SignerError::Password { layer, source })?;
+            let signer = PrivateKeySigner::decrypt_keystore(path, password).map_err(|source| {
+                SignerError::Keystore {
+                    layer,
+                    path: path.to_path_buf(),
+                    source,
+                }
+            })?;
+            return Ok(signer.into());
+        }
+
+        // The remote signer URL is required by the CLI when a public key is set.
+        if let (Some(public_key), Some(url)) = (remote_signer_public_key, &self.remote_signer_url) {
+            return Ok(RemoteSigner::new(url.clone(), public_key).into());
+        }
+
+        if let Some(phrase) = &self.mnemonic {
+            let signer =
+                derive_from_mnemonic(phrase, &self.mnemonic_derivation_path, mnemonic_index)
+                    .map_err(|source| SignerError::Mnemonic { layer, source })?;
+            return Ok(signer.into());
+        }
+
+        Err(SignerError::Missing(layer))
+    }
+}
+
+/// Reads the keystore password from `password_file`, or prompts for it on the terminal.
+fn keystore_password(
+    layer: &str,
+    keystore: &Path,
+    password_file: Option<&Path>,
+) -> std::io::Result<String> {
+    match password_file {
+        // Ignore the trailing newline most editors add to the file.
+        Some(file) => Ok(std::fs::read_to_string(file)?
+            .trim_end_matches(['\r', '\n'])
+            .to_string()),
+        None => rpassword::prompt_password(format!(
+            "Enter the password of the {layer} keystore {}: ",
+            keystore.display()
+        )),
+    }
+}
+
+/// Reads one hex-encoded private key per line, skipping blank lines and `#` comments.
+pub fn read_keys_file(
+    layer: &'static str,
+    path: &Path,
+) -> Result<Vec<PrivateKeySigner>, SignerError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| SignerError::KeysFile {
+        layer,
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let keys = contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            // Don't echo the line in the error, as it most likely holds a secret.
+            line.parse().map_err(|_| SignerError::InvalidKey {
+                layer,
+                path: path.to_path_buf(),
+                line: line_number,
+            })
+        })
+        .collect::<Result<Vec<PrivateKeySigner>, _>>()?;
+
+    if keys.is_empty() {
+        return Err(SignerError::EmptyKeysFile {
+            layer,
+            path: path.to_path_buf(),
+        });
+    }
+
+    Ok(keys)
+}
+
+/// Derives the signer at `{derivation_path}/{index}` from a BIP-39 English mnemonic.
+pub fn derive_from_mnemonic(
+    phrase: &str,
+    derivation_path: &str,
+    index: u32,
+) -> Result<PrivateKeySigner, LocalSignerError> {
+    MnemonicBuilder::<English>::default()
+        .phrase(phrase)
+        .derivation_path(format!("{}/{index}", derivation_path.trim_end_matches('/')))?
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::address;
+
+    use super::*;
+
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+    const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0";
+
+    #[test]
+    fn derives_the_well_known_dev_accounts() {
+        let first = derive_from_mnemonic(TEST_MNEMONIC, DEFAULT_DERIVATION_PATH, 0).unwrap();
+        assert_eq!(
+            first.address(),
+            address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266")
+        );
+
+        // A trailing slash in the derivation path is tolerated.
+        let second = derive_from_mnemonic(TEST_MNEMONIC, "m/44'/60'/0'/0/", 1).unwrap();
+        assert_eq!(
+            second.address(),
+            address!("0x70997970C51812dc3A010C7d01b50e0d17dc79C8")
+        );
+    }
+
+    #[test]
+    fn decrypts_keystore_with_password_file() {
+        let dir = std::env::temp_dir().join(format!("fi-toolbox-keystore-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (signer, name) =
+            PrivateKeySigner::new_keystore(&dir, &mut rand::thread_rng(), "hunter2", None).unwrap();
+        let password_file = dir.join("password");
+        std::fs::write(&password_file, "hunter2\n").unwrap();
+
+        let opts = SignerOptions {
+            l1_keystore: Some(dir.join(name)),
+            l1_keystore_password_file: Some(password_file),
+            ..Default::default()
+        };
+        let wallet = opts.l1_wallet().unwrap();
+        assert_eq!(wallet.default_signer().address(), signer.address());
+        assert!(matches!(opts.l2_wallet(), Err(SignerError::Missing("L2"))));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_keys_files() {
+        let path = std::env::temp_dir().join(format!("fi-toolbox-keys-{}", std::process::id()));
+        let first = derive_from_mnemonic(TEST_MNEMONIC, DEFAULT_DERIVATION_PATH, 0).unwrap();
+        let second = derive_from_mnemonic(TEST_MNEMONIC, DEFAULT_DERIVATION_PATH, 1).unwrap();
+        std::fs::write(
+            &path,
+            format!(
+                "# spam accounts\n{}\n\n  {}  \n",
+                first.to_bytes(),
+                second.to_bytes()
+            ),
+        )
+        .unwrap();
+
+        let keys = read_keys_file("L1", &path).unwrap();
+        let addresses: Vec<_> = keys.iter().map(|key| key.address()).collect();
+        assert_eq!(addresses, vec![first.address(), second.address()]);
+
+        std::fs::write(&path, format!("{}\nnot a key\n", first.to_bytes())).unwrap();
+        assert!(matches!(
+            read_keys_file("L1", &path),
+            Err(SignerError::InvalidKey { line: 2, .. })
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+use std::{cell::Cell, fmt};
+
+use alloy::{
+    primitives::{Address, U256, utils::format_ether},
+    providers::{Provider, WalletProvider},
+};
+use futures::future::join_all;
+use tokio::{sync::Mutex, time::sleep};
+
+use crate::{
+    cli::{SendCmdOptions, SpamCmdOptions},
+    fees::FeeCapError,
+    preflight::PreflightError,
+    retry::{backoff, is_transient},
+    schedule::Schedule,
+    send::{SendError, SendOutcome, SentInclusion},
+    state::{InclusionRecord, StateStore, resume_l2_nonce},
+    wallet_provider::DefaultWalletProvider,
+};
+
+/// The running totals of a spam run, shared by all spam accounts.
+#[derive(Debug, Default)]
+pub struct SpamStats {
+    sent: Cell<u64>,
+    confirmed: Cell<u64>,
+    skipped: Cell<u64>,
+    failed: Cell<u64>,
+    spent: Cell<U256>,
+    budget: Option<SpamBudget>,
+}
+
+impl SpamStats {
+    /// Builds the stats of a spam run that can spend at most `budget` wei, if any.
+    pub fn new(budget: Option<U256>) -> Self {
+        Self {
+            budget: budget.map(SpamBudget::new),
+            ..Default::default()
+        }
+    }
+
+    /// The budget of the spam run, if any.
+    pub fn budget(&self) -> Option<&SpamBudget> {
+        self.budget.as_ref()
+    }
+
+    /// Records a forced inclusion transaction that was mined.
+    pub fn record(&self, outcome: &SendOutcome) {
+        self.sent.set(self.sent.get() + 1);
+        if outcome.saved {
+            self.confirmed.set(self.confirmed.get() + 1);
+        }
+        self.spent.set(self.spent.get() + outcome.cost);
+    }
+
+    /// Records a forced inclusion skipped because of the fee caps.
+    pub fn record_skip(&self) {
+        self.skipped.set(self.skipped.get() + 1);
+    }
+
+    /// Records a failed forced inclusion, and the gas it spent if its transaction was mined.
+    pub fn record_failure(&self, err: &eyre::Report) {
+        self.failed.set(self.failed.get() + 1);
+        match err.downcast_ref::<SendError>() {
+            Some(SendError::Reverted { cost, .. }) => {
+                self.sent.set(self.sent.get() + 1);
+                self.spent.set(self.spent.get() + *cost);
+            }
+            Some(SendError::ReceiptTimeout { .. }) => self.sent.set(self.sent.get() + 1),
+            _ => {}
+        }
+    }
+
+    /// The amount of wei spent so far.
+    pub fn spent(&self) -> U256 {
+        self.spent.get()
+    }
+}
+
+impl fmt::Display for SpamStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} sent, {} confirmed, {} skipped, {} failed, spent {} ETH",
+            self.sent.get(),
+            self.confirmed.get(),
+            self.skipped.get(),
+            self.failed.get(),
+            format_ether(self.spent.get())
+        )
+    }
+}
+
+/// The spam budget shared by all spam accounts.
+///
+/// The worst-case cost of each forced inclusion is reserved before it's broadcast, and settled
+/// to its actual cost once mined, so that concurrent accounts can't overshoot the budget.
+#[derive(Debug)]
+pub struct SpamBudget {
+    remaining: Cell<U256>,
+}
+
+impl SpamBudget {
+    /// Builds a budget of `budget` wei.
+    pub fn new(budget: U256) -> Self {
+        Self {
+            remaining: Cell::new(budget),
+        }
+    }
+
+    /// Reserves `cost` wei from the remaining budget, if it's enough.
+    pub fn reserve(&self, cost: U256) -> Result<(), FeeCapError> {
+        let remaining = self.remaining.get();
+        if cost > remaining {
+            return Err(FeeCapError::Budget { cost, remaining });
+        }
+        self.remaining.set(remaining - cost);
+        Ok(())
+    }
+
+    /// Settles a reservation of `reserved` wei, of which only `spent` wei were spent.
+    pub fn settle(&self, reserved: U256, spent: U256) {
+        let unspent = reserved.saturating_sub(spent);
+        self.remaining.set(self.remaining.get() + unspent);
+    }
+}
+
+/// A spam run, whose accounts send forced inclusions concurrently on the same task, sharing
+/// its schedule, stats and budget.
+pub struct SpamRun<'a> {
+    opts: &'a SpamCmdOptions,
+    state: &'a StateStore,
+    schedule: Mutex<Schedule>,
+    stats: SpamStats,
+}
+
+impl<'a> SpamRun<'a> {
+    /// Builds a spam run with the given options, tracking its forced inclusions in `state`.
+    pub fn new(opts: &'a SpamCmdOptions, state: &'a StateStore) -> Self {
+        Self {
+            opts,
+            state,
+            schedule: Mutex::new(Schedule::new(opts.load)),
+            stats: SpamStats::new(opts.budget_eth),
+        }
+    }
+
+    /// The budget of the spam run, if any.
+    pub fn budget(&self) -> Option<&SpamBudget> {
+        self.stats.budget()
+    }
+
+    /// Runs the spam `accounts`, see [`Self::account`], and prints the summary of the run.
+    pub async fn run<F>(&self, accounts: impl IntoIterator<Item = F>) -> eyre::Result<()>
+    where
+        F: Future<Output = eyre::Result<()>>,
+    {
+        // Let the other accounts finish even if one of them stops with an error, so that the
+        // summary covers the whole run.
+        let results = join_all(accounts).await;
+
+        println!(
+            "🏁 Spam finished after {} forced inclusions: {}",
+            self.schedule.lock().await.slots(),
+            self.stats
+        );
+        results.into_iter().collect()
+    }
+
+    /// Send forced inclusion transactions from a single spam account in the slots of the
+    /// schedule, until the schedule ends, the budget is exhausted or too many forced
+    /// inclusions failed in a row.
+    ///
+    /// The forced inclusions left pending by a previous run are first resumed with
+    /// `resume_pending`. Each forced inclusion is then sent with `send_one`, and the queue
+    /// size is read with `queue_size`, as they differ between forks.
+    ///
+    /// Transient failures are retried with an exponential backoff, resyncing the L2 nonce
+    /// from the chain. The L1 nonce is read from the chain on every send.
+    pub async fn account<P, R, Q, QFut, S, SFut>(
+        &self,
+        l1: &P,
+        l2: &DefaultWalletProvider,
+        store: Address,
+        resume_pending: R,
+        mut queue_size: Q,
+        mut send_one: S,
+    ) -> eyre::Result<()>
+    where
+        P: Provider + WalletProvider,
+        R: Future<Output = eyre::Result<Vec<InclusionRecord>>>,
+        Q: FnMut() -> QFut,
+        QFut: Future<Output = eyre::Result<u64>>,
+        S: FnMut(SendCmdOptions) -> SFut,
+        SFut: Future<Output = eyre::Result<Option<SentInclusion>>>,
+    {
+        let opts = self.opts;
+        let stats = &self.stats;
+        let l1_sender = l1.default_signer_address();
+
+        // Resume the forced inclusions left pending by a previous run, instead of sending them again.
+        resume_pending.await?;
+
+        // Track the L2 nonce manually, so that it only advances once a forced inclusion
+        // has been saved, and skipped sends don't leave a nonce gap.
+        let sender = l2.default_signer_address();
+        let mut l2_nonce = resume_l2_nonce(self.state, store, l2, sender).await?;
+        let mut failures = 0;
+
+        loop {
+            if !self.schedule.lock().await.next(l1, &mut queue_size).await? {
+                return Ok(());
+            }
+
+            let mut send_opts = SendCmdOptions {
+                blob_sidecar_version: opts.blob_sidecar_version,
+                fee_caps: opts.fee_caps,
+                fee_bump: opts.fee_bump,
+                receipt_timeout_secs: opts.receipt_timeout_secs,
+                ..Default::default()
+            };
+
+            let mut retries = 0;
+            let result = loop {
+                send_opts.l2_nonce = Some(l2_nonce);
+                match send_one(send_opts.clone()).await {
+                    Err(e) if is_transient(&e) && retries < opts.retry.max_retries => {
+                        let delay = backoff(&opts.retry, retries);
+                        retries += 1;
+                        println!(
+                            "🔁 Transient error sending forced inclusion from {l1_sender}, retrying in {}s ({retries}/{}): {e}",
+                            delay.as_secs(),
+                            opts.retry.max_retries
+                        );
+                        sleep(delay).await;
+                        l2_nonce = resync_nonce(l2, l2_nonce).await;
+                    }
+                    result => break result,
+                }
+            };
+
+            match result {
+                Ok(sent) => {
+                    failures = 0;
+                    // Spam runs are never dry runs, so every forced inclusion was sent.
+                    if let Some(sent) = sent {
+                        stats.record(&sent.outcome);
+                        if sent.outcome.saved {
+                            l2_nonce += 1;
+                        }
+                    }
+                }
+                Err(e) => {
+                    if let Some(err) = e.downcast_ref::<FeeCapError>() {
+                        if matches!(err, FeeCapError::Budget { .. }) {
+                            println!("💸 Spam budget exhausted, stopping {l1_sender}");
+                            return Ok(());
+                        }
+                        stats.record_skip();
+                        println!("⏭️ Skipping forced inclusion from {l1_sender}: {err}");
+                        continue;
+                    }
+
+                    // Don't retry an unfunded signer until the failure threshold.
+                    if e.downcast_ref::<PreflightError>().is_some() {
+                        return Err(e);
+                    }
+
+                    stats.record_failure(&e);
+                    failures += 1;
+                    println!("❌ Forced inclusion batch from {l1_sender} failed! Error: {e:#}");
+                    if failures >= opts.retry.max_consecutive_failures {
+                        println!("🛑 Stopping {l1_sender} after {failures} consecutive failures");
+                        return Err(e);
+                    }
+                    l2_nonce = resync_nonce(l2, l2_nonce).await;
+                }
+            }
+        }
+    }
+}
+
+/// Resyncs the manually tracked L2 nonce after a failure, in case the chain moved past it.
+///
+/// The chain nonce lags behind while forced inclusions are still queued, so the tracked nonce
+/// is never lowered. It's kept as is if the chain can't be reached.
+async fn resync_nonce(l2: &DefaultWalletProvider, nonce: u64) -> u64 {
+    let sender = l2.default_signer_address();
+    match l2.get_transaction_count(sender).pending().await {
+        Ok(chain_nonce) => nonce.max(chain_nonce),
+        Err(_) => nonce,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserves_the_spam_budget_before_spending_it() {
+        let budget = SpamBudget::new(U256::from(100));
+
+        // Two concurrent sends can't both reserve more than the budget.
+        budget.reserve(U256::from(60)).unwrap();
+        assert!(matches!(
+            budget.reserve(U256::from(60)),
+            Err(FeeCapError::Budget { remaining, .. }) if remaining == U256::from(40)
+        ));
+
+        // The unspent part of a settled reservation is available again.
+        budget.settle(U256::from(60), U256::from(20));
+        budget.reserve(U256::from(80)).unwrap();
+        assert!(budget.reserve(U256::from(1)).is_err());
+    }
+}
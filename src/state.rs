@@ -0,0 +1,647 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use alloy::{
+    primitives::{Address, B256, U256, utils::format_ether},
+    providers::Provider,
+    rpc::types::{Filter, TransactionReceipt},
+};
+use rusqlite::{Connection, Row, params};
+use tokio::time::sleep;
+
+use crate::send::{SendError, SendOutcome};
+
+/// The interval between receipt checks while waiting for the forced inclusions of a previous run.
+const RESUME_POLL_INTERVAL: Duration = Duration::from_secs(12);
+
+/// How long to wait for the forced inclusions of a previous run when no receipt timeout is set.
+pub const RESUME_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// The schema of the state store, created on first use.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS inclusions (
+    store TEXT NOT NULL,
+    l1_sender TEXT NOT NULL,
+    l1_nonce INTEGER NOT NULL,
+    l1_tx_hashes TEXT NOT NULL,
+    l1_block INTEGER,
+    l2_sender TEXT,
+    l2_nonce INTEGER,
+    l2_tx_hashes TEXT NOT NULL,
+    blob_hashes TEXT NOT NULL,
+    fee_wei TEXT NOT NULL,
+    cost_wei TEXT,
+    queue_index INTEGER,
+    status TEXT NOT NULL,
+    sent_at INTEGER NOT NULL,
+    PRIMARY KEY (store, l1_sender, l1_nonce)
+);
+";
+
+/// An error returned by the local state store.
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+pub enum StateError {
+    #[error("failed to open the state store at {}: {source}", .path.display())]
+    Open {
+        path: PathBuf,
+        source: rusqlite::Error,
+    },
+    #[error("state store query failed: {0}")]
+    Query(#[from] rusqlite::Error),
+    #[error("invalid {column} in the state store: {value}")]
+    Invalid { column: &'static str, value: String },
+}
+
+/// The status of a sent forced inclusion, as last seen by `send`, `spam` or `status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InclusionStatus {
+    /// The L1 transaction was broadcast, but hasn't been seen mined yet.
+    Pending,
+    /// None of the L1 transactions was mined, and their nonce was used by another one.
+    Dropped,
+    /// The L1 transaction reverted.
+    Reverted,
+    /// The forced inclusion was saved, and is waiting in the queue.
+    Queued,
+    /// The forced inclusion left the queue, processed by a proposal.
+    Processed,
+    /// The force-included L2 transactions were found on L2.
+    Included,
+}
+
+impl InclusionStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Dropped => "dropped",
+            Self::Reverted => "reverted",
+            Self::Queued => "queued",
+            Self::Processed => "processed",
+            Self::Included => "included",
+        }
+    }
+
+    /// Whether the status can't change anymore.
+    pub fn is_final(self) -> bool {
+        matches!(self, Self::Dropped | Self::Reverted | Self::Included)
+    }
+}
+
+impl fmt::Display for InclusionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for InclusionStatus {
+    type Err = StateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "pending" => Self::Pending,
+            "dropped" => Self::Dropped,
+            "reverted" => Self::Reverted,
+            "queued" => Self::Queued,
+            "processed" => Self::Processed,
+            "included" => Self::Included,
+            _ => {
+                return Err(StateError::Invalid {
+                    column: "status",
+                    value: s.to_string(),
+                });
+            }
+        })
+    }
+}
+
+/// A forced inclusion sent to the store, identified by its L1 sender and nonce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionRecord {
+    /// The forced inclusion store the forced inclusion was sent to.
+    pub store: Address,
+    /// The signer of the L1 transaction.
+    pub l1_sender: Address,
+    /// The nonce of the L1 transaction, shared by all its fee-bumped replacements.
+    pub l1_nonce: u64,
+    /// The hashes of the broadcast L1 transactions, or only the mined one once known.
+    pub l1_tx_hashes: Vec<B256>,
+    /// The L1 block the transaction was mined in.
+    pub l1_block: Option<u64>,
+    /// The signer of the force-included L2 transaction, unless it was pre-signed.
+    pub l2_sender: Option<Address>,
+    /// The nonce of the force-included L2 transaction.
+    pub l2_nonce: Option<u64>,
+    /// The hashes of the force-included L2 transactions.
+    pub l2_tx_hashes: Vec<B256>,
+    /// The versioned hashes of the blobs carrying the L2 transactions.
+    pub blob_hashes: Vec<B256>,
+    /// The forced inclusion fee paid to the store, in wei.
+    pub fee_wei: U256,
+    /// The total amount of wei spent by the L1 transaction, once mined.
+    pub cost_wei: Option<U256>,
+    /// The index of the forced inclusion in the queue, once saved.
+    pub queue_index: Option<u64>,
+    /// The last seen status.
+    pub status: InclusionStatus,
+    /// When the forced inclusion was first broadcast, in seconds since the Unix epoch.
+    pub sent_at: u64,
+}
+
+impl InclusionRecord {
+    /// Creates the record of a forced inclusion about to be broadcast.
+    pub fn new(
+        store: Address,
+        l1_sender: Address,
+        l2_sender: Option<Address>,
+        l2_nonce: u64,
+        l2_tx_hashes: Vec<B256>,
+        blob_hashes: Vec<B256>,
+        fee_wei: U256,
+    ) -> Self {
+        let sent_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Self {
+            store,
+            l1_sender,
+            l1_nonce: 0,
+            l1_tx_hashes: Vec::new(),
+            l1_block: None,
+            l2_sender,
+            l2_nonce: Some(l2_nonce),
+            l2_tx_hashes,
+            blob_hashes,
+            fee_wei,
+            cost_wei: None,
+            queue_index: None,
+            status: InclusionStatus::Pending,
+            sent_at,
+        }
+    }
+
+    /// Records the mined L1 transaction, and the queue index of the forced inclusion if saved.
+    pub fn mined(&mut self, receipt: &TransactionReceipt, queue_index: Option<u64>) {
+        let outcome = SendOutcome::from_receipt(receipt, self.fee_wei);
+        self.l1_tx_hashes = vec![receipt.transaction_hash];
+        self.l1_block = receipt.block_number;
+        self.cost_wei = Some(outcome.cost);
+        self.queue_index = queue_index;
+        self.status = if outcome.saved {
+            InclusionStatus::Queued
+        } else {
+            InclusionStatus::Reverted
+        };
+    }
+
+    fn from_row(row: &Row<'_>) -> Result<Self, StateError> {
+        let l1_block: Option<i64> = row.get("l1_block")?;
+        let queue_index: Option<i64> = row.get("queue_index")?;
+        let cost_wei: Option<String> = row.get("cost_wei")?;
+        let l1_nonce: i64 = row.get("l1_nonce")?;
+        let l2_sender: Option<String> = row.get("l2_sender")?;
+        let l2_nonce: Option<i64> = row.get("l2_nonce")?;
+        let sent_at: i64 = row.get("sent_at")?;
+
+        Ok(Self {
+            store: parse("store", &row.get::<_, String>("store")?)?,
+            l1_sender: parse("l1_sender", &row.get::<_, String>("l1_sender")?)?,
+            l1_nonce: l1_nonce as u64,
+            l1_tx_hashes: parse_hashes("l1_tx_hashes", &row.get::<_, String>("l1_tx_hashes")?)?,
+            l1_block: l1_block.map(|block| block as u64),
+            l2_sender: l2_sender
+                .map(|sender| parse("l2_sender", &sender))
+                .transpose()?,
+            l2_nonce: l2_nonce.map(|nonce| nonce as u64),
+            l2_tx_hashes: parse_hashes("l2_tx_hashes", &row.get::<_, String>("l2_tx_hashes")?)?,
+            blob_hashes: parse_hashes("blob_hashes", &row.get::<_, String>("blob_hashes")?)?,
+            fee_wei: parse("fee_wei", &row.get::<_, String>("fee_wei")?)?,
+            cost_wei: cost_wei.map(|cost| parse("cost_wei", &cost)).transpose()?,
+            queue_index: queue_index.map(|index| index as u64),
+            status: row.get::<_, String>("status")?.parse()?,
+            sent_at: sent_at as u64,
+        })
+    }
+}
+
+impl fmt::Display for InclusionRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nonce={}", self.l1_sender, self.l1_nonce)?;
+        if let Some(index) = self.queue_index {
+            write!(f, " queue_index={index}")?;
+        }
+        write!(f, " status={}", self.status)?;
+        if let Some(tx_hash) = self.l1_tx_hashes.last() {
+            write!(f, " l1_tx={tx_hash}")?;
+        }
+        write!(f, " fee={} ETH", format_ether(self.fee_wei))?;
+        if let Some(cost) = self.cost_wei {
+            write!(f, " cost={} ETH", format_ether(cost))?;
+        }
+        for tx_hash in &self.l2_tx_hashes {
+            write!(f, " l2_tx={tx_hash}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A local SQLite database remembering the sent forced inclusions across runs.
+#[derive(Debug)]
+pub struct StateStore {
+    conn: Connection,
+}
+
+impl StateStore {
+    /// Opens the state store at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self, StateError> {
+        let open = || {
+            let conn = Connection::open(path)?;
+            conn.execute_batch(SCHEMA)?;
+            Ok(conn)
+        };
+
+        open()
+            .map(|conn| Self { conn })
+            .map_err(|source| StateError::Open {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    /// Inserts or updates the record of a forced inclusion.
+    pub fn save(&self, record: &InclusionRecord) -> Result<(), StateError> {
+        self.conn.execute(
+            "INSERT INTO inclusions (
+                store, l1_sender, l1_nonce, l1_tx_hashes, l1_block, l2_sender, l2_nonce,
+                l2_tx_hashes, blob_hashes, fee_wei, cost_wei, queue_index, status, sent_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+            ON CONFLICT (store, l1_sender, l1_nonce) DO UPDATE SET
+                l1_tx_hashes = excluded.l1_tx_hashes,
+                l1_block = excluded.l1_block,
+                l2_sender = excluded.l2_sender,
+                l2_nonce = excluded.l2_nonce,
+                l2_tx_hashes = excluded.l2_tx_hashes,
+                blob_hashes = excluded.blob_hashes,
+                fee_wei = excluded.fee_wei,
+                cost_wei = excluded.cost_wei,
+                queue_index = excluded.queue_index,
+                status = excluded.status,
+                sent_at = excluded.sent_at",
+            params![
+                record.store.to_string(),
+                record.l1_sender.to_string(),
+                record.l1_nonce as i64,
+                join_hashes(&record.l1_tx_hashes),
+                record.l1_block.map(|block| block as i64),
+                record.l2_sender.map(|sender| sender.to_string()),
+                record.l2_nonce.map(|nonce| nonce as i64),
+                join_hashes(&record.l2_tx_hashes),
+                join_hashes(&record.blob_hashes),
+                record.fee_wei.to_string(),
+                record.cost_wei.map(|cost| cost.to_string()),
+                record.queue_index.map(|index| index as i64),
+                record.status.as_str(),
+                record.sent_at as i64,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Saves a record, only printing a warning on failure, as failing to track a forced
+    /// inclusion that was already broadcast shouldn't fail its send.
+    pub fn save_or_warn(&self, record: &InclusionRecord) {
+        if let Err(e) = self.save(record) {
+            println!("⚠️ Failed to save forced inclusion to the state store: {e}");
+        }
+    }
+
+    /// Lists the forced inclusions sent to `store`, oldest first.
+    pub fn list(&self, store: Address) -> Result<Vec<InclusionRecord>, StateError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM inclusions WHERE store = ?1 ORDER BY sent_at, l1_sender, l1_nonce",
+        )?;
+        let mut rows = stmt.query([store.to_string()])?;
+
+        let mut records = Vec::new();
+        while let Some(row) = rows.next()? {
+            records.push(InclusionRecord::from_row(row)?);
+        }
+        Ok(records)
+    }
+
+    /// Lists the forced inclusions sent to `store` by `l1_sender` whose status can still
+    /// change, oldest first.
+    pub fn unfinished(
+        &self,
+        store: Address,
+        l1_sender: Address,
+    ) -> Result<Vec<InclusionRecord>, StateError> {
+        let mut records = self.list(store)?;
+        records.retain(|record| record.l1_sender == l1_sender && !record.status.is_final());
+        Ok(records)
+    }
+
+    /// The L2 nonce following the forced inclusions of `l2_sender` that are still on their way
+    /// to L2, and so aren't reflected by its L2 nonce yet.
+    pub fn next_l2_nonce(
+        &self,
+        store: Address,
+        l2_sender: Address,
+    ) -> Result<Option<u64>, StateError> {
+        let max: Option<i64> = self.conn.query_row(
+            "SELECT MAX(l2_nonce) FROM inclusions
+            WHERE store = ?1 AND l2_sender = ?2 AND status IN ('pending', 'queued')",
+            [store.to_string(), l2_sender.to_string()],
+            |row| row.get(0),
+        )?;
+        Ok(max.map(|nonce| nonce as u64 + 1))
+    }
+}
+
+/// Refreshes the status of the unfinished forced inclusions from the chain, saving the changes.
+///
+/// `head` is the current head of the queue, and `queue_index` derives the queue index of a
+/// forced inclusion from its receipt, as both are exposed differently by each fork. The L2
+/// inclusion is only checked when `l2` is set.
+pub async fn refresh<P, L2, F, Fut>(
+    state: &StateStore,
+    records: &mut [InclusionRecord],
+    l1: &P,
+    l2: Option<&L2>,
+    head: u64,
+    mut queue_index: F,
+) -> eyre::Result<()>
+where
+    P: Provider,
+    L2: Provider,
+    F: FnMut(TransactionReceipt) -> Fut,
+    Fut: Future<Output = eyre::Result<Option<u64>>>,
+{
+    for record in records
+        .iter_mut()
+        .filter(|record| !record.status.is_final())
+    {
+        let before = record.clone();
+
+        if record.status == InclusionStatus::Pending {
+            let mut receipt = find_receipt(l1, &record.l1_tx_hashes).await?;
+            if receipt.is_none() {
+                // Once its nonce is used, the forced inclusion was either replaced by another
+                // transaction, e.g. by the cancel command, or mined since we looked for its
+                // receipt, so look for it again before dropping it.
+                let nonce = l1.get_transaction_count(record.l1_sender).await?;
+                if nonce > record.l1_nonce {
+                    receipt = find_receipt(l1, &record.l1_tx_hashes).await?;
+                    if receipt.is_none() {
+                        record.status = InclusionStatus::Dropped;
+                    }
+                }
+            }
+
+            if let Some(receipt) = receipt {
+                let mut index = None;
+                if receipt.status() {
+                    index = queue_index(receipt.clone()).await?;
+                }
+                record.mined(&receipt, index);
+            }
+        }
+
+        if record.status == InclusionStatus::Queued
+            && record.queue_index.is_some_and(|index| index < head)
+        {
+            record.status = InclusionStatus::Processed;
+        }
+
+        if matches!(
+            record.status,
+            InclusionStatus::Queued | InclusionStatus::Processed
+        ) && let Some(l2) = l2
+            && !record.l2_tx_hashes.is_empty()
+        {
+            let mut included = true;
+            for tx_hash in &record.l2_tx_hashes {
+                included &= l2.get_transaction_receipt(*tx_hash).await?.is_some();
+            }
+            if included {
+                record.status = InclusionStatus::Included;
+            }
+        }
+
+        if *record != before {
+            state.save(record)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the receipt of the first mined transaction among `tx_hashes`, newest first.
+async fn find_receipt<P: Provider>(
+    l1: &P,
+    tx_hashes: &[B256],
+) -> eyre::Result<Option<TransactionReceipt>> {
+    for tx_hash in tx_hashes.iter().rev() {
+        if let Some(receipt) = l1.get_transaction_receipt(*tx_hash).await? {
+            return Ok(Some(receipt));
+        }
+    }
+    Ok(None)
+}
+
+/// Reloads the forced inclusions of `l1_sender` that were still pending when a previous run
+/// stopped, and waits for them to be mined or dropped, so that they aren't sent twice.
+///
+/// Returns the resumed forced inclusions, with their refreshed status. See [`refresh`] for
+/// `head` and `queue_index`. Fails if some are still pending after `timeout`, or after
+/// [`RESUME_TIMEOUT`] if not set, as they may never be mined, e.g. when underpriced.
+#[allow(clippy::too_many_arguments)]
+pub async fn resume<P, L2, F, Fut>(
+    state: &StateStore,
+    store: Address,
+    l1_sender: Address,
+    l1: &P,
+    l2: Option<&L2>,
+    head: u64,
+    timeout: Option<Duration>,
+    mut queue_index: F,
+) -> eyre::Result<Vec<InclusionRecord>>
+where
+    P: Provider,
+    L2: Provider,
+    F: FnMut(TransactionReceipt) -> Fut,
+    Fut: Future<Output = eyre::Result<Option<u64>>>,
+{
+    let mut records = state.unfinished(store, l1_sender)?;
+    records.retain(|record| record.status == InclusionStatus::Pending);
+
+    let timeout = timeout.unwrap_or(RESUME_TIMEOUT);
+    let started = Instant::now();
+    loop {
+        refresh(state, &mut records, l1, l2, head, &mut queue_index).await?;
+        let pending: Vec<_> = records
+            .iter()
+            .filter(|record| record.status == InclusionStatus::Pending)
+            .collect();
+        let Some(oldest) = pending.iter().map(|record| record.l1_nonce).min() else {
+            break;
+        };
+
+        if started.elapsed() >= timeout {
+            return Err(SendError::StuckPending {
+                l1_sender,
+                nonce: oldest,
+                timeout,
+            }
+            .into());
+        }
+
+        println!(
+            "⏳ Waiting for {} pending forced inclusions of {l1_sender} from a previous run",
+            pending.len()
+        );
+        sleep(RESUME_POLL_INTERVAL).await;
+    }
+
+    for record in &records {
+        println!("🔁 Resumed forced inclusion {record}");
+    }
+    Ok(records)
+}
+
+/// The next L2 nonce of `l2_sender`, skipping the nonces of its forced inclusions that are
+/// still on their way to L2, as the nonce managers of the providers start from the L2 nonce
+/// on every run.
+pub async fn resume_l2_nonce<P: Provider>(
+    state: &StateStore,
+    store: Address,
+    l2: &P,
+    l2_sender: Address,
+) -> eyre::Result<u64> {
+    let nonce = l2.get_transaction_count(l2_sender).pending().await?;
+    let next = state.next_l2_nonce(store, l2_sender)?;
+    Ok(next.map_or(nonce, |next| next.max(nonce)))
+}
+
+/// Finds the log emitted by the forced inclusion `store` with the `event` signature in
+/// `receipt`, and returns its block and the number of forced inclusions saved after it in
+/// the same block, from which the queue index is derived with the queue tail at that block.
+pub async fn saved_in_block<P: Provider>(
+    l1: &P,
+    receipt: &TransactionReceipt,
+    store: Address,
+    event: B256,
+) -> eyre::Result<Option<(u64, u64)>> {
+    let saved = receipt
+        .logs()
+        .iter()
+        .find(|log| log.address() == store && log.topic0() == Some(&event));
+    let (Some(saved), Some(block)) = (saved, receipt.block_number) else {
+        return Ok(None);
+    };
+
+    let filter = Filter::new()
+        .address(store)
+        .event_signature(event)
+        .from_block(block)
+        .to_block(block);
+    let later = l1
+        .get_logs(&filter)
+        .await?
+        .iter()
+        .filter(|log| log.log_index > saved.log_index)
+        .count();
+
+    Ok(Some((block, later as u64)))
+}
+
+fn join_hashes(hashes: &[B256]) -> String {
+    hashes
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn parse_hashes(column: &'static str, value: &str) -> Result<Vec<B256>, StateError> {
+    value
+        .split(',')
+        .filter(|hash| !hash.is_empty())
+        .map(|hash| parse(column, hash))
+        .collect()
+}
+
+fn parse<T: FromStr>(column: &'static str, value: &str) -> Result<T, StateError> {
+    value.parse().map_err(|_| StateError::Invalid {
+        column,
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{address, b256};
+
+    use super::*;
+
+    #[test]
+    fn saves_and_updates_records() {
+        let path = std::env::temp_dir().join(format!("fi-toolbox-state-{}.db", std::process::id()));
+        let state = StateStore::open(&path).unwrap();
+
+        let store = address!("0x00000000000000000000000000000000000000f1");
+        let mut record = InclusionRecord::new(
+            store,
+            address!("0x00000000000000000000000000000000000000a1"),
+            Some(address!("0x00000000000000000000000000000000000000b1")),
+            3,
+            vec![b256!(
+                "0x1111111111111111111111111111111111111111111111111111111111111111"
+            )],
+            vec![b256!(
+                "0x0122222222222222222222222222222222222222222222222222222222222222"
+            )],
+            U256::from(10_000_000_000_000_000u64),
+        );
+        record.l1_nonce = 7;
+        record.l1_tx_hashes = vec![b256!(
+            "0x3333333333333333333333333333333333333333333333333333333333333333"
+        )];
+        state.save(&record).unwrap();
+
+        // A fee bump replaces the broadcast hashes of the same nonce.
+        record.l1_tx_hashes.push(b256!(
+            "0x4444444444444444444444444444444444444444444444444444444444444444"
+        ));
+        state.save(&record).unwrap();
+        assert_eq!(state.list(store).unwrap(), [record.clone()]);
+
+        record.status = InclusionStatus::Queued;
+        record.queue_index = Some(42);
+        record.cost_wei = Some(U256::from(12_000_000_000_000_000u64));
+        state.save(&record).unwrap();
+        assert_eq!(state.list(store).unwrap(), [record.clone()]);
+        assert!(state.list(Address::ZERO).unwrap().is_empty());
+
+        // Queued forced inclusions hold on to their L2 nonce until included on L2.
+        let l2_sender = record.l2_sender.unwrap();
+        assert_eq!(state.next_l2_nonce(store, l2_sender).unwrap(), Some(4));
+        record.status = InclusionStatus::Included;
+        state.save(&record).unwrap();
+        assert_eq!(state.next_l2_nonce(store, l2_sender).unwrap(), None);
+        assert!(
+            state
+                .unfinished(store, record.l1_sender)
+                .unwrap()
+                .is_empty()
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,14 +1,100 @@
 use alloy::{
     network::EthereumWallet,
     providers::{
-        RootProvider,
+        ProviderBuilder, RootProvider,
         fillers::{FillProvider, JoinFill, WalletFiller},
         utils::JoinedRecommendedFillers,
     },
 };
 
+use crate::{
+    cli::{Cli, MissingConfigError, SpamCmdOptions},
+    signer::SignerError,
+};
+
 /// Alias to the joined recommended fillers + wallet filler for Ethereum wallets.
 pub type JoinedWalletFillers = JoinFill<JoinedRecommendedFillers, WalletFiller<EthereumWallet>>;
 
 /// Alias to the default wallet provider with all recommended fillers (read + write).
 pub type DefaultWalletProvider = FillProvider<JoinedWalletFillers, RootProvider>;
+
+/// Alias to the default provider with all recommended fillers, without a wallet (read-only).
+pub type DefaultProvider = FillProvider<JoinedRecommendedFillers, RootProvider>;
+
+/// The L1 and L2 signers of a spam worker.
+#[derive(Debug)]
+pub struct SpamAccount {
+    /// Provider with the L1 signer wallet, sending the forced inclusion transactions.
+    pub l1: DefaultWalletProvider,
+    /// Provider with the L2 signer wallet, signing the force-included transactions.
+    pub l2: DefaultWalletProvider,
+}
+
+impl Cli {
+    /// Connects a read-only provider to the L1, which doesn't need any signer.
+    pub fn l1_provider(&self) -> DefaultProvider {
+        ProviderBuilder::new().connect_http(self.l1_rpc_url.clone())
+    }
+
+    /// Connects a read-only provider to the L2, if its RPC URL is set.
+    pub fn l2_provider(&self) -> Option<DefaultProvider> {
+        let url = self.l2_rpc_url.clone()?;
+        Some(ProviderBuilder::new().connect_http(url))
+    }
+
+    /// Connects a provider to the L1 with the L1 signer wallet.
+    pub fn l1_wallet_provider(&self) -> eyre::Result<DefaultWalletProvider> {
+        Ok(ProviderBuilder::new()
+            .wallet(self.signers.l1_wallet()?)
+            .connect_http(self.l1_rpc_url.clone()))
+    }
+
+    /// Connects a provider to the L2 with the L2 signer wallet.
+    pub fn l2_wallet_provider(&self) -> eyre::Result<DefaultWalletProvider> {
+        let url = self
+            .l2_rpc_url
+            .clone()
+            .ok_or(MissingConfigError("L2_RPC_URL"))?;
+        Ok(ProviderBuilder::new()
+            .wallet(self.signers.l2_wallet()?)
+            .connect_http(url))
+    }
+
+    /// Connects the providers of each spam account, pairing the L1 and L2 signers in order.
+    ///
+    /// Each account gets its own providers, and so its own nonce management.
+    pub fn spam_accounts(&self, opts: &SpamCmdOptions) -> eyre::Result<Vec<SpamAccount>> {
+        let l2_url = self
+            .l2_rpc_url
+            .clone()
+            .ok_or(MissingConfigError("L2_RPC_URL"))?;
+        let l1_wallets = self
+            .signers
+            .l1_wallets(opts.l1_keys_file.as_deref(), opts.mnemonic_accounts)?;
+        let l2_wallets = self
+            .signers
+            .l2_wallets(opts.l2_keys_file.as_deref(), opts.mnemonic_accounts)?;
+        if l2_wallets.len() < l1_wallets.len() {
+            return Err(SignerError::TooFewL2Signers {
+                l1: l1_wallets.len(),
+                l2: l2_wallets.len(),
+            }
+            .into());
+        }
+
+        let accounts = l1_wallets
+            .into_iter()
+            .zip(l2_wallets)
+            .map(|(l1, l2)| SpamAccount {
+                l1: ProviderBuilder::new()
+                    .wallet(l1)
+                    .connect_http(self.l1_rpc_url.clone()),
+                l2: ProviderBuilder::new()
+                    .wallet(l2)
+                    .connect_http(l2_url.clone()),
+            })
+            .collect();
+
+        Ok(accounts)
+    }
+}